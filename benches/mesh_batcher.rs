@@ -0,0 +1,54 @@
+// 对比 Triangle 累加和 MeshBatcher 合并一个区块 (16 * 16 个方块) 的耗时
+// cargo bench --bench mesh_batcher
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use cube_world::util::{MeshBatcher, Triangle};
+
+const PLAIN_SIZE: usize = 16;
+const ROUNDS: u32 = 20;
+
+fn add_folding(cube: &Triangle) -> Mesh {
+    let mut r: Triangle = Triangle::new(Vec::new(), Vec::new(), Vec::new());
+    for x in 0..PLAIN_SIZE {
+        for z in 0..PLAIN_SIZE {
+            r = r + cube.clone() * Transform::from_xyz(x as f32, 0.0, z as f32);
+        }
+    }
+    r.build()
+}
+
+fn batcher(cube: &Triangle) -> Mesh {
+    let mut batcher = MeshBatcher::with_capacity(
+        cube.points.len() * PLAIN_SIZE * PLAIN_SIZE,
+        cube.index_list().len() * PLAIN_SIZE * PLAIN_SIZE,
+    );
+    for x in 0..PLAIN_SIZE {
+        for z in 0..PLAIN_SIZE {
+            batcher.push_triangle(cube, Transform::from_xyz(x as f32, 0.0, z as f32));
+        }
+    }
+    batcher.build()
+}
+
+fn bench(name: &str, f: impl Fn() -> Mesh) -> Duration {
+    // 预热
+    std::hint::black_box(f());
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(f());
+    }
+    let per_round = (Instant::now() - start) / ROUNDS;
+    println!("{name}: {:.3} ms", per_round.as_secs_f64() * 1000.0);
+    per_round
+}
+
+fn main() {
+    let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+    let folding = bench("add folding", || add_folding(&cube));
+    let batched = bench("mesh batcher", || batcher(&cube));
+    println!(
+        "speedup: {:.1}x",
+        folding.as_secs_f64() / batched.as_secs_f64()
+    );
+}
@@ -1,8 +1,6 @@
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
-use bevy::render::{
-    mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology,
-};
+use cube_world::util::Triangle;
 use simdnoise::*;
 use smooth_bevy_cameras::{LookTransform, Smoother};
 
@@ -137,7 +135,6 @@ fn get_mesh(region_x: i32, region_z: i32) -> Mesh {
 fn create_plain_mesh(plain_size: u32, height_mesh: &Vec<Vec<f32>>, transform: Transform) -> Mesh {
     let mut attribute_position: Vec<[f32; 3]> = Vec::new();
     let mut attribute_uv_0: Vec<[f32; 2]> = Vec::new();
-    let mut attribute_normal: Vec<[f32; 3]> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
 
     for (x_index, z_list) in height_mesh.iter().enumerate() {
@@ -152,9 +149,6 @@ fn create_plain_mesh(plain_size: u32, height_mesh: &Vec<Vec<f32>>, transform: Tr
             // uv
             let uv_size = 1f32 / plain_size as f32;
             attribute_uv_0.push([uv_size * x_index as f32, uv_size * z_index as f32]);
-
-            // 法线
-            attribute_normal.push([0.0, 1.0, 0.0]);
         }
     }
 
@@ -171,12 +165,19 @@ fn create_plain_mesh(plain_size: u32, height_mesh: &Vec<Vec<f32>>, transform: Tr
         }
     }
 
-    Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    // 法线按起伏平滑计算
+    Triangle::new_indexed(
+        attribute_position
+            .into_iter()
+            .map(Vec3::from_array)
+            .collect(),
+        vec![],
+        attribute_uv_0
+            .into_iter()
+            .map(|uv| Vec3::new(uv[0], uv[1], 0.0))
+            .collect(),
+        indices,
     )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, attribute_position)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, attribute_uv_0)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, attribute_normal)
-    .with_inserted_indices(Indices::U32(indices))
+    .smooth_normals(std::f32::consts::PI)
+    .build()
 }
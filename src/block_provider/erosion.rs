@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::WORLD_SEED;
+
+#[derive(Clone, Debug)]
+pub struct ErosionConfig {
+    // 缓存单位: 一个 tile 的边长 (方块)
+    pub tile_size: i32,
+    // tile 四周额外计算的边距, 相邻 tile 在边距里交叉淡化, 不能超过 tile_size 的一半
+    pub margin: i32,
+    // 每个格子投放的水滴数量
+    pub droplets_per_cell: f32,
+    pub max_lifetime: u32,
+    pub inertia: f32,
+    pub capacity: f32,
+    pub min_slope: f32,
+    pub erode_speed: f32,
+    pub deposit_speed: f32,
+    pub evaporate_speed: f32,
+    pub gravity: f32,
+    // 热侵蚀: 相邻格子允许的最大高差
+    pub talus: f32,
+    pub thermal_rate: f32,
+    pub thermal_iterations: u32,
+    pub max_cached_tiles: usize,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> Self {
+        ErosionConfig {
+            tile_size: 64,
+            margin: 16,
+            droplets_per_cell: 0.6,
+            max_lifetime: 40,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_slope: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.02,
+            gravity: 4.0,
+            talus: 1.5,
+            thermal_rate: 0.5,
+            thermal_iterations: 8,
+            max_cached_tiles: 256,
+        }
+    }
+}
+
+pub struct Erosion {
+    config: ErosionConfig,
+    cache: Mutex<HashMap<(i32, i32), Arc<Vec<f32>>>>,
+}
+
+impl Erosion {
+    pub fn new(config: ErosionConfig) -> Erosion {
+        Erosion {
+            config,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 取侵蚀后的高度 [x][z], raw 为未侵蚀的原始高度生成函数 (x, z, size_x, size_z)
+    // 相邻 tile 的边距互相重叠, 在重叠处按距离交叉淡化, 所以 tile 边界上没有台阶
+    pub fn heights(
+        &self,
+        x: i32,
+        z: i32,
+        size_x: usize,
+        size_z: usize,
+        raw: impl Fn(i32, i32, usize, usize) -> Vec<Vec<f32>>,
+    ) -> Vec<Vec<f32>> {
+        let tile_size = self.config.tile_size;
+        let margin = self.config.margin;
+        let size = tile_size + margin * 2;
+        let mut rv = vec![vec![0f32; size_z]; size_x];
+        let mut tiles: HashMap<(i32, i32), Arc<Vec<f32>>> = HashMap::new();
+        for (x_index, z_list) in rv.iter_mut().enumerate() {
+            for (z_index, height) in z_list.iter_mut().enumerate() {
+                let wx = x + x_index as i32;
+                let wz = z + z_index as i32;
+                let mut sum = 0f32;
+                let mut total = 0f32;
+                for tile_x in wx.div_euclid(tile_size) - 1..=wx.div_euclid(tile_size) + 1 {
+                    let lx = wx - tile_x * tile_size;
+                    let weight_x = self.blend_weight(lx);
+                    if weight_x <= 0.0 {
+                        continue;
+                    }
+                    for tile_z in wz.div_euclid(tile_size) - 1..=wz.div_euclid(tile_size) + 1 {
+                        let lz = wz - tile_z * tile_size;
+                        let weight = weight_x * self.blend_weight(lz);
+                        if weight <= 0.0 {
+                            continue;
+                        }
+                        let tile = tiles
+                            .entry((tile_x, tile_z))
+                            .or_insert_with(|| self.tile(tile_x, tile_z, &raw));
+                        sum += tile[((lx + margin) * size + lz + margin) as usize] * weight;
+                        total += weight;
+                    }
+                }
+                *height = sum / total;
+            }
+        }
+        rv
+    }
+
+    // tile 内坐标 local 处的混合权重, 边距里从 tile 外缘的 0 线性升到 1,
+    // 相邻两个 tile 在重叠处的权重之和为 1
+    fn blend_weight(&self, local: i32) -> f32 {
+        let tile_size = self.config.tile_size;
+        let margin = self.config.margin;
+        if margin <= 0 {
+            return if (0..tile_size).contains(&local) {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let ramp = (2 * margin) as f32;
+        let left = (local + margin) as f32 + 0.5;
+        let right = (tile_size + margin - local) as f32 - 0.5;
+        (left.min(right) / ramp).clamp(0.0, 1.0)
+    }
+
+    // 带边距的整块侵蚀结果 [x][z], 边长 tile_size + margin * 2
+    fn tile(
+        &self,
+        tile_x: i32,
+        tile_z: i32,
+        raw: &impl Fn(i32, i32, usize, usize) -> Vec<Vec<f32>>,
+    ) -> Arc<Vec<f32>> {
+        if let Some(tile) = self.cache.lock().unwrap().get(&(tile_x, tile_z)) {
+            return tile.clone();
+        }
+
+        let tile_size = self.config.tile_size;
+        let margin = self.config.margin;
+        let size = (tile_size + margin * 2) as usize;
+        let mut map: Vec<f32> = raw(
+            tile_x * tile_size - margin,
+            tile_z * tile_size - margin,
+            size,
+            size,
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let seed = ((WORLD_SEED as u64) << 40) ^ ((tile_x as u32 as u64) << 20) ^ (tile_z as u32 as u64);
+        let mut rng = StdRng::seed_from_u64(seed);
+        hydraulic(&mut map, size, &self.config, &mut rng);
+        thermal(&mut map, size, &self.config);
+        let tile = Arc::new(map);
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.config.max_cached_tiles {
+            // 淘汰离当前 tile 最远的缓存
+            let farthest = cache
+                .keys()
+                .max_by_key(|(x, z)| (x - tile_x).abs() + (z - tile_z).abs())
+                .copied();
+            if let Some(key) = farthest {
+                cache.remove(&key);
+            }
+        }
+        cache.insert((tile_x, tile_z), tile.clone());
+        tile
+    }
+}
+
+// 双线性插值求高度和梯度
+fn height_and_gradient(map: &[f32], size: usize, x: f32, z: f32) -> (f32, f32, f32) {
+    let cx = x as usize;
+    let cz = z as usize;
+    let fx = x - cx as f32;
+    let fz = z - cz as f32;
+    let i = cx * size + cz;
+    let h00 = map[i];
+    let h01 = map[i + 1];
+    let h10 = map[i + size];
+    let h11 = map[i + size + 1];
+    let gx = (h10 - h00) * (1.0 - fz) + (h11 - h01) * fz;
+    let gz = (h01 - h00) * (1.0 - fx) + (h11 - h10) * fx;
+    let h = h00 * (1.0 - fx) * (1.0 - fz) + h10 * fx * (1.0 - fz) + h01 * (1.0 - fx) * fz + h11 * fx * fz;
+    (h, gx, gz)
+}
+
+// 按双线性权重修改四个角的高度
+fn splat(map: &mut [f32], size: usize, x: f32, z: f32, amount: f32) {
+    let cx = x as usize;
+    let cz = z as usize;
+    let fx = x - cx as f32;
+    let fz = z - cz as f32;
+    let i = cx * size + cz;
+    map[i] += amount * (1.0 - fx) * (1.0 - fz);
+    map[i + size] += amount * fx * (1.0 - fz);
+    map[i + 1] += amount * (1.0 - fx) * fz;
+    map[i + size + 1] += amount * fx * fz;
+}
+
+// 水滴冲刷
+fn hydraulic(map: &mut [f32], size: usize, config: &ErosionConfig, rng: &mut StdRng) {
+    let limit = (size - 1) as f32;
+    let droplets = (size * size) as f32 * config.droplets_per_cell;
+    for _ in 0..droplets as u32 {
+        let mut x = rng.gen_range(0.0..limit);
+        let mut z = rng.gen_range(0.0..limit);
+        let mut dir_x = 0f32;
+        let mut dir_z = 0f32;
+        let mut speed = 1f32;
+        let mut water = 1f32;
+        let mut sediment = 0f32;
+
+        for _ in 0..config.max_lifetime {
+            let (height, gx, gz) = height_and_gradient(map, size, x, z);
+
+            dir_x = dir_x * config.inertia - gx * (1.0 - config.inertia);
+            dir_z = dir_z * config.inertia - gz * (1.0 - config.inertia);
+            let len = (dir_x * dir_x + dir_z * dir_z).sqrt();
+            if len < f32::EPSILON {
+                break;
+            }
+            dir_x /= len;
+            dir_z /= len;
+
+            let old_x = x;
+            let old_z = z;
+            x += dir_x;
+            z += dir_z;
+            if x < 0.0 || z < 0.0 || x >= limit || z >= limit {
+                break;
+            }
+
+            let (new_height, _, _) = height_and_gradient(map, size, x, z);
+            let delta = new_height - height;
+            let capacity = (-delta).max(config.min_slope) * speed * water * config.capacity;
+
+            if sediment > capacity || delta > 0.0 {
+                // 上坡时填平坑, 否则沉积多余的泥沙
+                let deposit = if delta > 0.0 {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * config.deposit_speed
+                };
+                sediment -= deposit;
+                splat(map, size, old_x, old_z, deposit);
+            } else {
+                let erode = ((capacity - sediment) * config.erode_speed).min(-delta);
+                sediment += erode;
+                splat(map, size, old_x, old_z, -erode);
+            }
+
+            speed = (speed * speed - delta * config.gravity).max(0.0).sqrt();
+            water *= 1.0 - config.evaporate_speed;
+        }
+    }
+}
+
+// 热侵蚀: 超过休止角的高差向低处滑落
+fn thermal(map: &mut [f32], size: usize, config: &ErosionConfig) {
+    let mut delta = vec![0f32; map.len()];
+    for _ in 0..config.thermal_iterations {
+        delta.iter_mut().for_each(|d| *d = 0.0);
+        for x in 0..size {
+            for z in 0..size {
+                let i = x * size + z;
+                for (nx, nz) in [(x + 1, z), (x, z + 1)] {
+                    if nx >= size || nz >= size {
+                        continue;
+                    }
+                    let j = nx * size + nz;
+                    let diff = map[i] - map[j];
+                    if diff.abs() > config.talus {
+                        let amount = (diff.abs() - config.talus) * config.thermal_rate * 0.5;
+                        let amount = amount.copysign(diff);
+                        delta[i] -= amount;
+                        delta[j] += amount;
+                    }
+                }
+            }
+        }
+        map.iter_mut().zip(delta.iter()).for_each(|(h, d)| *h += d);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_edges_are_continuous() {
+        let erosion = Erosion::new(ErosionConfig::default());
+        let raw = |x: i32, z: i32, size_x: usize, size_z: usize| {
+            (0..size_x)
+                .map(|i| {
+                    (0..size_z)
+                        .map(|j| {
+                            let (wx, wz) = ((x + i as i32) as f32, (z + j as i32) as f32);
+                            (wx * 0.11).sin() * (wz * 0.07).cos() * 12.0 + wx * 0.2
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        let tile_size = erosion.config.tile_size;
+        let heights = erosion.heights(-tile_size, -tile_size, 128, 128, raw);
+
+        // 跨过 tile 边界的高差和 tile 内部的高差差不多
+        let mut edge = (0f32, 0);
+        let mut inside = (0f32, 0);
+        for x in 0..127 {
+            let crosses = (x + 1) % tile_size as usize == 0;
+            for (a, b) in heights[x].iter().zip(heights[x + 1].iter()) {
+                let delta = (b - a).abs();
+                let sum = if crosses { &mut edge } else { &mut inside };
+                sum.0 += delta;
+                sum.1 += 1;
+            }
+        }
+        let edge = edge.0 / edge.1 as f32;
+        let inside = inside.0 / inside.1 as f32;
+        assert!(edge < inside * 1.2, "edge {} inside {}", edge, inside);
+    }
+}
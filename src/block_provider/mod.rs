@@ -1,34 +1,116 @@
 use bevy::{
-    ecs::{component::Component, system::Commands},
+    ecs::{
+        component::Component,
+        resource::Resource,
+        system::{Commands, Res},
+    },
     pbr::StandardMaterial,
     render::mesh::Mesh,
+    transform::components::Transform,
 };
+use bevy_rapier3d::prelude::Collider;
+use erosion::ErosionConfig;
+use noise_graph::{Fractal, NoiseNode};
 use normal::NormalGenerator;
+use smooth::{Overhang, SmoothGenerator};
 
+pub mod erosion;
+pub mod model;
+pub mod noise_graph;
 pub mod normal;
+pub mod smooth;
+
+// 世界种子
+pub const WORLD_SEED: i32 = 1;
 
 pub trait MapGenerator {
     fn generate_block(&self, x: i32, y: i32, z: i32) -> Mesh;
     fn generate_height_map(&self, x: i32, y: i32, z: i32) -> Vec<Vec<f32>>;
+    // 任意矩形范围的高度 [x][z], 起点为世界方块坐标
+    fn generate_height_area(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>>;
+    // 区块的刚体碰撞和它的世界位置, 区块里没有地面时为 None
+    fn generate_collider(&self, x: i32, y: i32, z: i32) -> Option<(Collider, Transform)>;
+}
+
+// 启动时使用的地图生成器, 默认是不侵蚀的普通地形;
+// 在 main 里把它换成 "eroded" 或 "smooth" 开启侵蚀或平滑地形
+#[derive(Resource, Clone, Debug)]
+pub struct MapGeneratorName(pub String);
+
+impl Default for MapGeneratorName {
+    fn default() -> Self {
+        MapGeneratorName("normal".to_string())
+    }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component)]
 pub struct MapGeneratorInfo {
     name: String,
+    generator: Box<dyn MapGenerator + Send + Sync>,
 }
 
 impl MapGeneratorInfo {
+    // 按名字选择地图生成器
+    pub fn new(name: &str) -> MapGeneratorInfo {
+        let terrain = load_terrain("assets/terrain/normal.ron");
+        let generator: Box<dyn MapGenerator + Send + Sync> = match name {
+            "eroded" => {
+                Box::new(NormalGenerator::new(terrain).with_erosion(ErosionConfig::default()))
+            }
+            "smooth" => Box::new(
+                SmoothGenerator::new(NormalGenerator::new(terrain)).with_overhang(Overhang {
+                    noise: Fractal {
+                        freq: 0.05,
+                        ..Fractal::default()
+                    },
+                    amplitude: 40.0,
+                }),
+            ),
+            _ => Box::new(NormalGenerator::new(terrain)),
+        };
+        MapGeneratorInfo {
+            name: name.to_string(),
+            generator,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn region_generate(&self, region_x: i32, region_y: i32, region_z: i32) -> Mesh {
-        NormalGenerator {}.generate_block(region_x, region_y, region_z)
+        self.generator.generate_block(region_x, region_y, region_z)
     }
 
     pub fn height_map(&self, region_x: i32, region_y: i32, region_z: i32) -> Vec<Vec<f32>> {
-        NormalGenerator{}.generate_height_map(region_x, region_y, region_z)
+        self.generator.generate_height_map(region_x, region_y, region_z)
+    }
+
+    pub fn height_area(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        self.generator.generate_height_area(x, z, size_x, size_z)
+    }
+
+    pub fn collider(
+        &self,
+        region_x: i32,
+        region_y: i32,
+        region_z: i32,
+    ) -> Option<(Collider, Transform)> {
+        self.generator.generate_collider(region_x, region_y, region_z)
+    }
+}
+
+// 读取地形噪声图, 失败时使用内置的默认地形
+fn load_terrain(path: &str) -> NoiseNode {
+    match NoiseNode::load(path) {
+        Ok(terrain) => terrain,
+        Err(e) => {
+            println!("load terrain {} fail: {}, use default", path, e);
+            NoiseNode::default()
+        }
     }
 }
 
-pub fn setup(mut commands: Commands) {
-    commands.spawn(MapGeneratorInfo {
-        name: "all".to_string(),
-    });
+pub fn setup(mut commands: Commands, generator_name: Res<MapGeneratorName>) {
+    commands.spawn(MapGeneratorInfo::new(&generator_name.0));
 }
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::util::{MeshBatcher, Triangle};
+use bevy::gltf::{Gltf, GltfMesh};
+use bevy::prelude::*;
+use bevy::render::mesh::MeshAabb;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockKind {
+    Sand,
+    Wood,
+    Stone,
+}
+
+impl BlockKind {
+    pub const ALL: [BlockKind; 3] = [BlockKind::Sand, BlockKind::Wood, BlockKind::Stone];
+
+    fn model(&self) -> &'static str {
+        match self {
+            BlockKind::Sand => "models/stylized_low-poly_sand_block.glb",
+            BlockKind::Wood => "models/stylized_low-poly_wood_crate.glb",
+            BlockKind::Stone => "models/stylized_low-poly_stone_block.glb",
+        }
+    }
+
+    // 低处是沙, 高处是石头
+    pub fn by_height(height: f32) -> BlockKind {
+        if height < -10.0 {
+            BlockKind::Sand
+        } else if height > 30.0 {
+            BlockKind::Stone
+        } else {
+            BlockKind::Wood
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockRenderMode {
+    // 每种方块的每个图元合并成一个网格
+    Merged,
+    // 每个方块一个实体, 共享网格和材质, 由渲染器自动合批实例化
+    Instanced,
+}
+
+// 模型的一个图元, 保留自己的材质
+#[derive(Clone)]
+pub struct BlockPrimitive {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+}
+
+#[derive(Clone)]
+pub struct BlockPrototype {
+    pub primitives: Vec<BlockPrimitive>,
+    // 把模型缩放并居中到 1*1*1 的格子
+    pub fit: Transform,
+}
+
+#[derive(Resource)]
+pub struct BlockModels {
+    gltf: HashMap<BlockKind, Handle<Gltf>>,
+    prototypes: HashMap<BlockKind, BlockPrototype>,
+}
+
+impl BlockModels {
+    pub fn load(asset_server: &AssetServer) -> BlockModels {
+        BlockModels {
+            gltf: BlockKind::ALL
+                .iter()
+                .map(|kind| (*kind, asset_server.load(kind.model())))
+                .collect(),
+            prototypes: HashMap::new(),
+        }
+    }
+
+    pub fn prototype(&self, kind: BlockKind) -> Option<&BlockPrototype> {
+        self.prototypes.get(&kind)
+    }
+
+    // 所有模型都加载完成
+    pub fn ready(&self) -> bool {
+        self.prototypes.len() == self.gltf.len()
+    }
+
+    // 模型加载后取第一个网格的所有图元;
+    // 加载失败或者没有可用网格的模型用普通立方体代替, 免得一直等下去
+    pub fn update(
+        &mut self,
+        asset_server: &AssetServer,
+        gltf_asset: &Assets<Gltf>,
+        gltf_mesh_asset: &Assets<GltfMesh>,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+    ) {
+        for (kind, handle) in self.gltf.iter() {
+            if self.prototypes.contains_key(kind) {
+                continue;
+            }
+            let load_state = asset_server.recursive_dependency_load_state(handle);
+            let prototype = if load_state.is_failed() {
+                println!("load {} failed: {:?}, use cube", kind.model(), load_state);
+                cube_prototype(meshes, materials)
+            } else if let Some(prototype) =
+                gltf_prototype(handle, gltf_asset, gltf_mesh_asset, meshes, materials)
+            {
+                prototype
+            } else if load_state.is_loaded() {
+                println!("{} has no usable mesh, use cube", kind.model());
+                cube_prototype(meshes, materials)
+            } else {
+                continue;
+            };
+            self.prototypes.insert(*kind, prototype);
+        }
+    }
+}
+
+fn gltf_prototype(
+    handle: &Handle<Gltf>,
+    gltf_asset: &Assets<Gltf>,
+    gltf_mesh_asset: &Assets<GltfMesh>,
+    meshes: &Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> Option<BlockPrototype> {
+    let gltf_mesh = gltf_asset
+        .get(handle)
+        .and_then(|gltf| gltf.meshes.first())
+        .and_then(|mesh_handle| gltf_mesh_asset.get(mesh_handle))?;
+    if gltf_mesh.primitives.is_empty() {
+        return None;
+    }
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for primitive in gltf_mesh.primitives.iter() {
+        let aabb = meshes.get(&primitive.mesh)?.compute_aabb()?;
+        min = min.min(aabb.min().into());
+        max = max.max(aabb.max().into());
+    }
+
+    let size = (max - min).max_element().max(f32::EPSILON);
+    let scale = 1.0 / size;
+    let fit =
+        Transform::from_translation(-(min + max) / 2.0 * scale).with_scale(Vec3::splat(scale));
+    let primitives = gltf_mesh
+        .primitives
+        .iter()
+        .map(|primitive| BlockPrimitive {
+            mesh: primitive.mesh.clone(),
+            material: primitive
+                .material
+                .clone()
+                .unwrap_or_else(|| materials.add(StandardMaterial::default())),
+        })
+        .collect();
+    Some(BlockPrototype { primitives, fit })
+}
+
+// 模型不可用时的 1*1*1 立方体
+fn cube_prototype(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> BlockPrototype {
+    BlockPrototype {
+        primitives: vec![BlockPrimitive {
+            mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
+            material: materials.add(StandardMaterial::default()),
+        }],
+        fit: Transform::IDENTITY,
+    }
+}
+
+// 区块内每种方块的位置, 区块内坐标
+pub fn block_transforms(height_map: &Vec<Vec<f32>>) -> HashMap<BlockKind, Vec<Transform>> {
+    let plain_size = 16usize;
+    let mut rv: HashMap<BlockKind, Vec<Transform>> = HashMap::new();
+    for (x_index, z_list) in height_map.iter().take(plain_size).enumerate() {
+        for (z_index, y_height) in z_list.iter().take(plain_size).enumerate() {
+            let cube_size = 1f32;
+            let x = cube_size * x_index as f32;
+            let y = *y_height;
+            let z = cube_size * z_index as f32;
+            rv.entry(BlockKind::by_height(y))
+                .or_default()
+                .push(Transform::from_xyz(x, y, z));
+        }
+    }
+    rv
+}
+
+// 合并模式: 每个 (方块, 图元) 一个网格
+pub fn region_by_models(
+    height_map: &Vec<Vec<f32>>,
+    block_models: &BlockModels,
+    meshes: &Assets<Mesh>,
+) -> Vec<(Mesh, Handle<StandardMaterial>)> {
+    let start = Instant::now();
+    let mut rv = Vec::new();
+    for (kind, transforms) in block_transforms(height_map) {
+        let Some(prototype) = block_models.prototype(kind) else {
+            continue;
+        };
+        for primitive in prototype.primitives.iter() {
+            let Some(mesh) = meshes.get(&primitive.mesh) else {
+                continue;
+            };
+            let block_tri = Triangle::from_mesh(mesh);
+            let mut batcher = MeshBatcher::with_capacity(
+                block_tri.points.len() * transforms.len(),
+                block_tri.index_list().len() * transforms.len(),
+            );
+            for transform in transforms.iter() {
+                batcher.push_triangle(&block_tri, *transform * prototype.fit);
+            }
+            rv.push((batcher.build(), primitive.material.clone()));
+        }
+    }
+    println!(
+        "region_by_models mesh time: {}",
+        (Instant::now() - start).as_secs_f32()
+    );
+    rv
+}
+
+// 实例模式: 每个方块的每个图元一个实体
+pub fn spawn_region_instances(
+    parent: &mut ChildSpawnerCommands,
+    height_map: &Vec<Vec<f32>>,
+    block_models: &BlockModels,
+) {
+    for (kind, transforms) in block_transforms(height_map) {
+        let Some(prototype) = block_models.prototype(kind) else {
+            continue;
+        };
+        for transform in transforms {
+            for primitive in prototype.primitives.iter() {
+                parent.spawn((
+                    Mesh3d(primitive.mesh.clone()),
+                    MeshMaterial3d(primitive.material.clone()),
+                    transform * prototype.fit,
+                ));
+            }
+        }
+    }
+}
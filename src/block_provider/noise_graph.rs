@@ -0,0 +1,373 @@
+use std::error::Error;
+
+use bevy::scene::ron;
+use serde::{Deserialize, Serialize};
+use simdnoise::NoiseBuilder;
+
+use super::WORLD_SEED;
+
+// 分形噪声参数, 默认值与 simdnoise 一致
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Fractal {
+    pub seed: i32,
+    pub freq: f32,
+    pub octaves: u8,
+    pub gain: f32,
+    pub lacunarity: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Fractal {
+            seed: WORLD_SEED,
+            freq: 0.02,
+            octaves: 3,
+            gain: 2.0,
+            lacunarity: 0.5,
+        }
+    }
+}
+
+// 可组合的地形噪声图, 可以写成 RON 文件
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum NoiseNode {
+    Fbm(Fractal),
+    Ridge(Fractal),
+    Turbulence(Fractal),
+    Cellular {
+        #[serde(default = "default_seed")]
+        seed: i32,
+        freq: f32,
+        #[serde(default = "default_jitter")]
+        jitter: f32,
+    },
+    Gradient {
+        #[serde(default = "default_seed")]
+        seed: i32,
+        freq: f32,
+    },
+    Constant(f32),
+    Add(Vec<NoiseNode>),
+    Multiply(Vec<NoiseNode>),
+    // source * scale + bias
+    Scale {
+        source: Box<NoiseNode>,
+        scale: f32,
+        #[serde(default)]
+        bias: f32,
+    },
+    Clamp {
+        source: Box<NoiseNode>,
+        min: f32,
+        max: f32,
+    },
+    // 通过控制点 (输入, 输出) 的 Catmull-Rom 曲线重新映射
+    Spline {
+        source: Box<NoiseNode>,
+        points: Vec<(f32, f32)>,
+    },
+    // 用 warp_x / warp_z 偏移 source 的采样坐标
+    DomainWarp {
+        source: Box<NoiseNode>,
+        warp_x: Box<NoiseNode>,
+        warp_z: Box<NoiseNode>,
+        strength: f32,
+    },
+    // control 小于 threshold 取 low, 大于取 high, falloff 范围内平滑过渡
+    Select {
+        control: Box<NoiseNode>,
+        low: Box<NoiseNode>,
+        high: Box<NoiseNode>,
+        threshold: f32,
+        #[serde(default)]
+        falloff: f32,
+    },
+}
+
+fn default_seed() -> i32 {
+    WORLD_SEED
+}
+
+fn default_jitter() -> f32 {
+    0.25
+}
+
+impl Default for NoiseNode {
+    // 原来写死的地形: fbm * 200
+    fn default() -> Self {
+        NoiseNode::Scale {
+            source: Box::new(NoiseNode::Fbm(Fractal {
+                freq: 0.01,
+                ..Fractal::default()
+            })),
+            scale: 200.0,
+            bias: 0.0,
+        }
+    }
+}
+
+impl NoiseNode {
+    pub fn load(path: &str) -> Result<NoiseNode, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&text)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    // 以世界方块坐标(x, z)为起点, 生成 size_x * size_z 的值 [x][z]
+    pub fn generate(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        self.sample(x as f32, z as f32, size_x, size_z)
+            .chunks(size_z)
+            .map(|chunk| chunk.to_vec())
+            .collect()
+    }
+
+    // 间隔为1的网格采样, 结果按 x 行排列
+    fn sample(&self, x: f32, z: f32, size_x: usize, size_z: usize) -> Vec<f32> {
+        let len = size_x * size_z;
+        match self {
+            // simdnoise 的 x 轴对应世界 z 轴
+            NoiseNode::Fbm(f) => {
+                NoiseBuilder::fbm_2d_offset(z, size_z, x, size_x)
+                    .with_seed(f.seed)
+                    .with_freq(f.freq)
+                    .with_octaves(f.octaves)
+                    .with_gain(f.gain)
+                    .with_lacunarity(f.lacunarity)
+                    .generate()
+                    .0
+            }
+            NoiseNode::Ridge(f) => {
+                NoiseBuilder::ridge_2d_offset(z, size_z, x, size_x)
+                    .with_seed(f.seed)
+                    .with_freq(f.freq)
+                    .with_octaves(f.octaves)
+                    .with_gain(f.gain)
+                    .with_lacunarity(f.lacunarity)
+                    .generate()
+                    .0
+            }
+            NoiseNode::Turbulence(f) => {
+                NoiseBuilder::turbulence_2d_offset(z, size_z, x, size_x)
+                    .with_seed(f.seed)
+                    .with_freq(f.freq)
+                    .with_octaves(f.octaves)
+                    .with_gain(f.gain)
+                    .with_lacunarity(f.lacunarity)
+                    .generate()
+                    .0
+            }
+            NoiseNode::Cellular { seed, freq, jitter } => {
+                NoiseBuilder::cellular_2d_offset(z, size_z, x, size_x)
+                    .with_seed(*seed)
+                    .with_freq(*freq)
+                    .with_jitter(*jitter)
+                    .generate()
+                    .0
+            }
+            NoiseNode::Gradient { seed, freq } => {
+                NoiseBuilder::gradient_2d_offset(z, size_z, x, size_x)
+                    .with_seed(*seed)
+                    .with_freq(*freq)
+                    .generate()
+                    .0
+            }
+            NoiseNode::Constant(value) => vec![*value; len],
+            NoiseNode::Add(nodes) => nodes.iter().fold(vec![0f32; len], |mut acc, node| {
+                let values = node.sample(x, z, size_x, size_z);
+                acc.iter_mut().zip(values).for_each(|(a, v)| *a += v);
+                acc
+            }),
+            NoiseNode::Multiply(nodes) => nodes.iter().fold(vec![1f32; len], |mut acc, node| {
+                let values = node.sample(x, z, size_x, size_z);
+                acc.iter_mut().zip(values).for_each(|(a, v)| *a *= v);
+                acc
+            }),
+            NoiseNode::Scale {
+                source,
+                scale,
+                bias,
+            } => source
+                .sample(x, z, size_x, size_z)
+                .into_iter()
+                .map(|v| v * scale + bias)
+                .collect(),
+            NoiseNode::Clamp { source, min, max } => source
+                .sample(x, z, size_x, size_z)
+                .into_iter()
+                .map(|v| v.clamp(*min, *max))
+                .collect(),
+            NoiseNode::Spline { source, points } => {
+                let mut points = points.clone();
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                source
+                    .sample(x, z, size_x, size_z)
+                    .into_iter()
+                    .map(|v| spline(&points, v))
+                    .collect()
+            }
+            NoiseNode::DomainWarp {
+                source,
+                warp_x,
+                warp_z,
+                strength,
+            } => {
+                let offset_x = warp_x.sample(x, z, size_x, size_z);
+                let offset_z = warp_z.sample(x, z, size_x, size_z);
+                let positions: Vec<(f32, f32)> = (0..len)
+                    .map(|i| {
+                        (
+                            x + (i / size_z) as f32 + offset_x[i] * strength,
+                            z + (i % size_z) as f32 + offset_z[i] * strength,
+                        )
+                    })
+                    .collect();
+
+                // 在覆盖所有偏移坐标的网格上采样, 再双线性插值
+                let (min_x, max_x, min_z, max_z) = positions.iter().fold(
+                    (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+                    |(a, b, c, d), (px, pz)| (a.min(*px), b.max(*px), c.min(*pz), d.max(*pz)),
+                );
+                let start_x = min_x.floor();
+                let start_z = min_z.floor();
+                let grid_x = (max_x.floor() - start_x) as usize + 2;
+                let grid_z = (max_z.floor() - start_z) as usize + 2;
+                let grid = source.sample(start_x, start_z, grid_x, grid_z);
+
+                positions
+                    .into_iter()
+                    .map(|(px, pz)| {
+                        let gx = px - start_x;
+                        let gz = pz - start_z;
+                        let cx = gx as usize;
+                        let cz = gz as usize;
+                        let fx = gx - cx as f32;
+                        let fz = gz - cz as f32;
+                        let i = cx * grid_z + cz;
+                        let h0 = grid[i] * (1.0 - fz) + grid[i + 1] * fz;
+                        let h1 = grid[i + grid_z] * (1.0 - fz) + grid[i + grid_z + 1] * fz;
+                        h0 * (1.0 - fx) + h1 * fx
+                    })
+                    .collect()
+            }
+            NoiseNode::Select {
+                control,
+                low,
+                high,
+                threshold,
+                falloff,
+            } => {
+                let control = control.sample(x, z, size_x, size_z);
+                let low = low.sample(x, z, size_x, size_z);
+                let high = high.sample(x, z, size_x, size_z);
+                (0..len)
+                    .map(|i| {
+                        let t = if *falloff > 0.0 {
+                            let t = ((control[i] - threshold + falloff) / (2.0 * falloff))
+                                .clamp(0.0, 1.0);
+                            t * t * (3.0 - 2.0 * t)
+                        } else if control[i] < *threshold {
+                            0.0
+                        } else {
+                            1.0
+                        };
+                        low[i] + (high[i] - low[i]) * t
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// Catmull-Rom 曲线, 超出控制点范围时取端点值
+fn spline(points: &[(f32, f32)], v: f32) -> f32 {
+    match points.len() {
+        0 => return v,
+        1 => return points[0].1,
+        _ => {}
+    }
+    if v <= points[0].0 {
+        return points[0].1;
+    }
+    let last = points.len() - 1;
+    if v >= points[last].0 {
+        return points[last].1;
+    }
+    let i = points.iter().rposition(|p| p.0 <= v).unwrap_or(0);
+    let p1 = points[i];
+    let p2 = points[i + 1];
+    let p0 = if i > 0 { points[i - 1] } else { p1 };
+    let p3 = if i + 2 <= last { points[i + 2] } else { p2 };
+    let span = p2.0 - p1.0;
+    if span <= f32::EPSILON {
+        return p2.1;
+    }
+    let t = (v - p1.0) / span;
+    let t2 = t * t;
+    let t3 = t2 * t;
+    // 切线按相邻区间宽度缩放, 非均匀控制点也保持连续
+    let m1 = if i > 0 {
+        (p2.1 - p0.1) / (p2.0 - p0.0) * span
+    } else {
+        p2.1 - p1.1
+    };
+    let m2 = if i + 2 <= last {
+        (p3.1 - p1.1) / (p3.0 - p1.0) * span
+    } else {
+        p2.1 - p1.1
+    };
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p1.1
+        + (t3 - 2.0 * t2 + t) * m1
+        + (-2.0 * t3 + 3.0 * t2) * p2.1
+        + (t3 - t2) * m2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_terrain_files() {
+        for path in ["assets/terrain/normal.ron", "assets/terrain/mountains.ron"] {
+            let terrain = NoiseNode::load(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            let heights = terrain.generate(0, 0, 4, 3);
+            assert_eq!((heights.len(), heights[0].len()), (4, 3));
+            assert!(heights.iter().flatten().all(|h| h.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_scale_clamp_select() {
+        let constant = |v: f32| Box::new(NoiseNode::Constant(v));
+        let value = |node: NoiseNode| node.generate(0, 0, 1, 1)[0][0];
+
+        let scale = NoiseNode::Scale {
+            source: constant(2.0),
+            scale: 3.0,
+            bias: 1.0,
+        };
+        assert_eq!(value(scale), 7.0);
+
+        let clamp = NoiseNode::Clamp {
+            source: constant(5.0),
+            min: -1.0,
+            max: 2.0,
+        };
+        assert_eq!(value(clamp), 2.0);
+
+        let select = |control: f32, falloff: f32| NoiseNode::Select {
+            control: constant(control),
+            low: constant(10.0),
+            high: constant(20.0),
+            threshold: 0.0,
+            falloff,
+        };
+        assert_eq!(value(select(-0.1, 0.0)), 10.0);
+        assert_eq!(value(select(0.1, 0.0)), 20.0);
+        // 过渡区间 [-0.4, 0.4] 内 t = 0.75, smoothstep 后为 0.84375
+        assert!((value(select(0.2, 0.4)) - 18.4375).abs() < 1e-4);
+    }
+}
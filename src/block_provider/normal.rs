@@ -1,7 +1,9 @@
 use std::time::Instant;
 
-use crate::util::Triangle;
+use crate::util::{MeshBatcher, Triangle};
 
+use super::erosion::{Erosion, ErosionConfig};
+use super::noise_graph::NoiseNode;
 use super::MapGenerator;
 use bevy::{
     ecs::{component::Component, system::Commands},
@@ -10,84 +12,127 @@ use bevy::{
     render::mesh::{Mesh, MeshBuilder, Meshable},
     transform::components::Transform,
 };
-use simdnoise::NoiseBuilder;
+use bevy_rapier3d::prelude::Collider;
 
-pub struct NormalGenerator;
+#[derive(Default)]
+pub struct NormalGenerator {
+    // 地形噪声图
+    terrain: NoiseNode,
+    // 可选的侵蚀后处理
+    erosion: Option<Erosion>,
+}
 
-impl MapGenerator for NormalGenerator {
-    fn generate_block(&self, region_x: i32, region_y: i32, region_z: i32) -> Mesh {
-        region_by_block(region_x, region_z)
+impl NormalGenerator {
+    pub fn new(terrain: NoiseNode) -> NormalGenerator {
+        NormalGenerator {
+            terrain,
+            erosion: None,
+        }
     }
-    fn generate_height_map(&self, region_x: i32, region_y: i32, region_z: i32) -> Vec<Vec<f32>> {
-        height_map_by_region(region_x, 0, region_z)
+
+    pub fn with_erosion(mut self, config: ErosionConfig) -> NormalGenerator {
+        self.erosion = Some(Erosion::new(config));
+        self
     }
-}
 
-fn height_map_by_region(region_x: i32, region_y: i32, region_z: i32) -> Vec<Vec<f32>> {
-    let plain_size = 16i32;
-    // [x1,x1,x1,...,x2,x2,x2,...,x3,x3,x3,....xy, xy,xy,...]
-    let (heights, min, max) = NoiseBuilder::fbm_2d_offset(
-        (region_z * plain_size) as f32,
-        (plain_size) as usize,
-        (region_x * plain_size) as f32,
-        (plain_size) as usize,
-    )
-    .with_seed(1)
-    .with_freq(0.01)
-    .generate();
-    let heights: Vec<f32> = heights.iter().map(|item| (item * 200f32).floor()).collect();
-    let plain_height: Vec<Vec<f32>> = heights
-        .chunks((plain_size) as usize)
-        .map(|chunk| {
-            let mut rv = chunk.to_vec();
-            rv
-        })
-        .collect();
-    return plain_height;
-}
+    // 未取整的高度 (侵蚀后)
+    pub(super) fn raw_height_area(
+        &self,
+        x: i32,
+        z: i32,
+        size_x: usize,
+        size_z: usize,
+    ) -> Vec<Vec<f32>> {
+        let raw = |x, z, size_x, size_z| self.terrain.generate(x, z, size_x, size_z);
+        match &self.erosion {
+            Some(erosion) => erosion.heights(x, z, size_x, size_z, raw),
+            None => raw(x, z, size_x, size_z),
+        }
+    }
 
-fn region_by_block(region_x: i32, region_z: i32) -> Mesh {
-    let plain_height: Vec<Vec<f32>> = get_map_height(region_x, region_z);
-
-    let start = Instant::now();
-    let mut collider_cube_mesh = create_cube_mesh(&plain_height);
-    collider_cube_mesh
-        .generate_tangents()
-        .expect("generate_tangents fail");
-    println!(
-        "create mesh time: {}",
-        (Instant::now() - start).as_secs_f32()
-    );
-    collider_cube_mesh
+    fn height_area(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        self.raw_height_area(x, z, size_x, size_z)
+            .into_iter()
+            .map(|z_list| z_list.into_iter().map(|height| height.floor()).collect())
+            .collect()
+    }
+
+    // 区块的高度 (16+1)*(16+1), 多一行用于和相邻区块衔接
+    fn get_map_height(&self, region_x: i32, region_z: i32) -> Vec<Vec<f32>> {
+        let plain_size = 16i32;
+        self.height_area(
+            region_x * plain_size,
+            region_z * plain_size,
+            (plain_size + 1) as usize,
+            (plain_size + 1) as usize,
+        )
+    }
+
+    fn region_by_block(&self, region_x: i32, region_z: i32) -> Mesh {
+        let plain_height: Vec<Vec<f32>> = self.get_map_height(region_x, region_z);
+
+        let start = Instant::now();
+        let collider_cube_mesh = create_cube_mesh(&plain_height);
+        println!(
+            "create mesh time: {}",
+            (Instant::now() - start).as_secs_f32()
+        );
+        collider_cube_mesh
+    }
 }
 
-fn get_map_height(region_x: i32, region_z: i32) -> Vec<Vec<f32>> {
-    let plain_size = 16i32;
-    // [x1,x1,x1,...,x2,x2,x2,...,x3,x3,x3,....xy, xy,xy,...]
-    let (heights, min, max) = NoiseBuilder::fbm_2d_offset(
-        (region_z * plain_size) as f32,
-        (plain_size + 1) as usize,
-        (region_x * plain_size) as f32,
-        (plain_size + 1) as usize,
-    )
-    .with_seed(1)
-    .with_freq(0.01)
-    .generate();
-    let heights: Vec<f32> = heights.iter().map(|item| (item * 200f32).floor()).collect();
-    let plain_height: Vec<Vec<f32>> = heights
-        .chunks((plain_size + 1) as usize)
-        .map(|chunk| {
-            let mut rv = chunk.to_vec();
-            rv
-        })
-        .collect();
-    return plain_height;
+impl MapGenerator for NormalGenerator {
+    fn generate_block(&self, region_x: i32, _region_y: i32, region_z: i32) -> Mesh {
+        self.region_by_block(region_x, region_z)
+    }
+    fn generate_height_map(&self, region_x: i32, _region_y: i32, region_z: i32) -> Vec<Vec<f32>> {
+        let plain_size = 16i32;
+        self.height_area(
+            region_x * plain_size,
+            region_z * plain_size,
+            plain_size as usize,
+            plain_size as usize,
+        )
+    }
+    fn generate_height_area(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        self.height_area(x, z, size_x, size_z)
+    }
+    fn generate_collider(
+        &self,
+        region_x: i32,
+        region_y: i32,
+        region_z: i32,
+    ) -> Option<(Collider, Transform)> {
+        let plain_size = 16i32;
+        let heights = self
+            .generate_height_map(region_x, region_y, region_z)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<f32>>();
+        let height_map_collider = Collider::heightfield(
+            heights,
+            plain_size as usize,
+            plain_size as usize,
+            Vec3::new(plain_size as f32, 1.0, plain_size as f32),
+        );
+
+        // 高度场以中心为原点, 方块以顶点为中心
+        let region_transform = Transform::from_xyz(
+            region_x as f32 * plain_size as f32 + (plain_size as f32 / 2.0) - 0.5f32,
+            0.5f32,
+            region_z as f32 * plain_size as f32 + (plain_size as f32 / 2.0) - 0.5f32,
+        );
+        Some((height_map_collider, region_transform))
+    }
 }
 
 fn create_cube_mesh(height_mesh: &Vec<Vec<f32>>) -> Mesh {
     let plain_size = 16usize;
-    let mut cube_transform: Vec<Transform> = Vec::<Transform>::new();
-    let mut cube_triangles: Vec<Triangle> = Vec::<Triangle>::new();
+    let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+    let mut batcher = MeshBatcher::with_capacity(
+        cube.points.len() * plain_size * plain_size,
+        cube.index_list().len() * plain_size * plain_size,
+    );
 
     for (x_index, z_list) in height_mesh.iter().take(plain_size).enumerate() {
         for (z_index, y_height) in z_list.iter().take(plain_size).enumerate() {
@@ -97,46 +142,18 @@ fn create_cube_mesh(height_mesh: &Vec<Vec<f32>>) -> Mesh {
             let y = *y_height;
             let z = cube_size * z_index as f32;
 
-            let mut cube_mesh: Triangle =
-                Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+            let mut cube_mesh: Triangle = cube.clone();
             cube_mesh.uv.iter_mut().for_each(|item: &mut Vec3| {
                 item.x = item.x / plain_size as f32 + x_index as f32 / plain_size as f32;
                 item.y = item.y / plain_size as f32 + z_index as f32 / plain_size as f32;
             });
-            cube_triangles.push(cube_mesh * Transform::from_xyz(x, y, z));
+            batcher.push_triangle(&cube_mesh, Transform::from_xyz(x, y, z));
         }
     }
 
-    let mut r: Triangle = Triangle::new(Vec::new(), Vec::new(), Vec::new());
-    for tri in cube_triangles {
-        r = r + tri;
+    let mut r = batcher.into_triangle();
+    if let Err(e) = r.generate_tangents() {
+        println!("generate_tangents fail: {}", e);
     }
-
     r.build()
 }
-
-fn region_by_mesh(region_x: i32, region_z: i32, mesh_obj: &Mesh) -> Mesh {
-    let plain_size = 16usize;
-    let plain_height: Vec<Vec<f32>> = get_map_height(region_x, region_z);
-
-    let start = Instant::now();
-    let mut block_tris = Vec::<Transform>::new();
-    for (x_index, z_list) in plain_height.iter().take(plain_size).enumerate() {
-        for (z_index, y_height) in z_list.iter().take(plain_size).enumerate() {
-            // 顶点
-            let cube_size = 1f32;
-            let x = cube_size * x_index as f32;
-            let y = *y_height;
-            let z = cube_size * z_index as f32;
-            let block_transform = Transform::from_xyz(x, y, z).with_scale(Vec3::new(1.3, 1.3, 1.3));
-            block_tris.push(block_transform);
-        }
-    }
-    let block_tri: Triangle = Triangle::from_mesh(mesh_obj);
-    let block_tri = block_tri * block_tris;
-    println!(
-        "region_by_mesh mesh time: {}",
-        (Instant::now() - start).as_secs_f32()
-    );
-    block_tri.build()
-}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::noise_graph::Fractal;
+use super::normal::NormalGenerator;
+use super::MapGenerator;
+use bevy::{
+    math::Vec3,
+    render::{
+        mesh::{Indices, Mesh},
+        render_asset::RenderAssetUsages,
+        render_resource::PrimitiveTopology,
+    },
+    transform::components::Transform,
+};
+use bevy_rapier3d::prelude::{Collider, ComputedColliderShape, TriMeshFlags};
+use simdnoise::NoiseBuilder;
+
+const PLAIN_SIZE: i32 = 16;
+// 采样点比区块多出一圈, 保证相邻区块的顶点完全一致
+const SAMPLES: usize = PLAIN_SIZE as usize + 3;
+// 缓存的区块网格数量, 显示和碰撞共用一次等值面提取
+const MAX_CACHED_SURFACES: usize = 256;
+
+// 悬崖和洞穴用的三维噪声
+#[derive(Clone, Debug)]
+pub struct Overhang {
+    pub noise: Fractal,
+    pub amplitude: f32,
+}
+
+// 平滑地形: 从密度场提取等值面 (surface nets)
+pub struct SmoothGenerator {
+    heights: NormalGenerator,
+    overhang: Option<Overhang>,
+    surfaces: Mutex<HashMap<(i32, i32), Arc<Mesh>>>,
+}
+
+impl SmoothGenerator {
+    pub fn new(heights: NormalGenerator) -> SmoothGenerator {
+        SmoothGenerator {
+            heights,
+            overhang: None,
+            surfaces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_overhang(mut self, overhang: Overhang) -> SmoothGenerator {
+        self.overhang = Some(overhang);
+        self
+    }
+
+    // 密度场 [x][y][z], 大于0为实心, 返回 (密度, 最低采样y, y方向采样数)
+    fn density(
+        &self,
+        start_x: i32,
+        start_z: i32,
+        size_x: usize,
+        size_z: usize,
+    ) -> (Vec<f32>, i32, usize) {
+        let heights = self
+            .heights
+            .raw_height_area(start_x, start_z, size_x, size_z);
+
+        let amplitude = self.overhang.as_ref().map_or(0.0, |o| o.amplitude);
+        let (min, max) = heights
+            .iter()
+            .flatten()
+            .fold((f32::MAX, f32::MIN), |(min, max), h| (min.min(*h), max.max(*h)));
+        let min_y = (min - amplitude).floor() as i32 - 2;
+        let size_y = ((max + amplitude).ceil() as i32 + 3 - min_y) as usize;
+
+        let overhang = self.overhang.as_ref().map(|o| {
+            NoiseBuilder::fbm_3d_offset(
+                start_z as f32,
+                size_z,
+                min_y as f32,
+                size_y,
+                start_x as f32,
+                size_x,
+            )
+            .with_seed(o.noise.seed)
+            .with_freq(o.noise.freq)
+            .with_octaves(o.noise.octaves)
+            .with_gain(o.noise.gain)
+            .with_lacunarity(o.noise.lacunarity)
+            .generate()
+            .0
+        });
+
+        let mut density = Vec::with_capacity(size_x * size_y * size_z);
+        for (x, z_list) in heights.iter().enumerate() {
+            for y in 0..size_y {
+                for (z, height) in z_list.iter().enumerate() {
+                    // 和方块地形一样, 地表在方块顶面 height + 0.5
+                    let mut d = height + 0.5 - (min_y + y as i32) as f32;
+                    if let Some(noise) = &overhang {
+                        d += noise[(x * size_y + y) * size_z + z] * amplitude;
+                    }
+                    density.push(d);
+                }
+            }
+        }
+        (density, min_y, size_y)
+    }
+
+    // 和网格一致的高度: 每一列最上面的等值面, 减去 0.5 换算成方块中心
+    fn surface_heights(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        let (density, min_y, size_y) = self.density(x, z, size_x, size_z);
+        (0..size_x)
+            .map(|x| {
+                (0..size_z)
+                    .map(|z| {
+                        let sample = |y: usize| density[(x * size_y + y) * size_z + z];
+                        let surface = match (0..size_y - 1).rev().find(|y| sample(*y) > 0.0) {
+                            Some(y) => {
+                                let (a, b) = (sample(y), sample(y + 1));
+                                (min_y + y as i32) as f32 + a / (a - b)
+                            }
+                            None => min_y as f32,
+                        };
+                        surface - 0.5
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn region_by_surface(&self, region_x: i32, region_z: i32) -> Arc<Mesh> {
+        if let Some(mesh) = self.surfaces.lock().unwrap().get(&(region_x, region_z)) {
+            return mesh.clone();
+        }
+
+        let start = Instant::now();
+        let (density, min_y, size_y) = self.density(
+            region_x * PLAIN_SIZE - 1,
+            region_z * PLAIN_SIZE - 1,
+            SAMPLES,
+            SAMPLES,
+        );
+        let mesh = Arc::new(surface_nets(&density, size_y, min_y));
+        println!(
+            "create smooth mesh time: {}",
+            (Instant::now() - start).as_secs_f32()
+        );
+
+        let mut surfaces = self.surfaces.lock().unwrap();
+        if surfaces.len() >= MAX_CACHED_SURFACES {
+            // 淘汰离当前区块最远的缓存
+            let farthest = surfaces
+                .keys()
+                .max_by_key(|(x, z)| (x - region_x).abs() + (z - region_z).abs())
+                .copied();
+            if let Some(key) = farthest {
+                surfaces.remove(&key);
+            }
+        }
+        surfaces.insert((region_x, region_z), mesh.clone());
+        mesh
+    }
+}
+
+impl MapGenerator for SmoothGenerator {
+    fn generate_block(&self, region_x: i32, _region_y: i32, region_z: i32) -> Mesh {
+        self.region_by_surface(region_x, region_z).as_ref().clone()
+    }
+    fn generate_height_map(&self, region_x: i32, _region_y: i32, region_z: i32) -> Vec<Vec<f32>> {
+        self.surface_heights(
+            region_x * PLAIN_SIZE,
+            region_z * PLAIN_SIZE,
+            PLAIN_SIZE as usize,
+            PLAIN_SIZE as usize,
+        )
+    }
+    fn generate_height_area(&self, x: i32, z: i32, size_x: usize, size_z: usize) -> Vec<Vec<f32>> {
+        self.surface_heights(x, z, size_x, size_z)
+    }
+    fn generate_collider(
+        &self,
+        region_x: i32,
+        _region_y: i32,
+        region_z: i32,
+    ) -> Option<(Collider, Transform)> {
+        let collider = surface_collider(&self.region_by_surface(region_x, region_z))?;
+        let transform = Transform::from_xyz(
+            (region_x * PLAIN_SIZE) as f32,
+            0.0,
+            (region_z * PLAIN_SIZE) as f32,
+        );
+        Some((collider, transform))
+    }
+}
+
+// 整个区块在地下或空中时没有等值面, 不需要碰撞体
+fn surface_collider(mesh: &Mesh) -> Option<Collider> {
+    if mesh.count_vertices() == 0 {
+        return None;
+    }
+    Collider::from_bevy_mesh(
+        mesh,
+        &ComputedColliderShape::TriMesh(TriMeshFlags::default()),
+    )
+}
+
+// 提取等值面, 顶点为区块内坐标
+fn surface_nets(density: &[f32], size_y: usize, min_y: i32) -> Mesh {
+    let sample = |x: usize, y: usize, z: usize| density[(x * size_y + y) * SAMPLES + z];
+    // 格子 (x, y, z) 由采样点 x..x+1, y..y+1, z..z+1 围成
+    let cells = SAMPLES - 1;
+    let cell_index = |x: usize, y: usize, z: usize| (x * (size_y - 1) + y) * cells + z;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut cell_vertex: Vec<u32> = vec![u32::MAX; cells * (size_y - 1) * cells];
+
+    // 每个跨过等值面的格子放一个顶点, 位置为各条边交点的平均
+    for x in 0..cells {
+        for y in 0..size_y - 1 {
+            for z in 0..cells {
+                let mut corners = [0f32; 8];
+                for (i, corner) in corners.iter_mut().enumerate() {
+                    *corner = sample(x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1));
+                }
+                let inside = corners.iter().filter(|d| **d > 0.0).count();
+                if inside == 0 || inside == 8 {
+                    continue;
+                }
+
+                let mut sum = Vec3::ZERO;
+                let mut count = 0f32;
+                for i in 0..8 {
+                    for axis in [1, 2, 4] {
+                        let j = i | axis;
+                        if j == i {
+                            continue;
+                        }
+                        let (a, b) = (corners[i], corners[j]);
+                        if (a > 0.0) == (b > 0.0) {
+                            continue;
+                        }
+                        let t = a / (a - b);
+                        let pa = Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+                        let pb = Vec3::new((j & 1) as f32, ((j >> 1) & 1) as f32, ((j >> 2) & 1) as f32);
+                        sum += pa.lerp(pb, t);
+                        count += 1.0;
+                    }
+                }
+
+                // 法线为密度梯度的反方向
+                let gradient = Vec3::new(
+                    (corners[1] + corners[3] + corners[5] + corners[7])
+                        - (corners[0] + corners[2] + corners[4] + corners[6]),
+                    (corners[2] + corners[3] + corners[6] + corners[7])
+                        - (corners[0] + corners[1] + corners[4] + corners[5]),
+                    (corners[4] + corners[5] + corners[6] + corners[7])
+                        - (corners[0] + corners[1] + corners[2] + corners[3]),
+                );
+                let normal = (-gradient).normalize_or(Vec3::Y);
+
+                // 采样点 0 对应区块坐标 -1
+                let position = sum / count + Vec3::new(x as f32 - 1.0, (y as i32 + min_y) as f32, z as f32 - 1.0);
+                cell_vertex[cell_index(x, y, z)] = positions.len() as u32;
+                positions.push(position.to_array());
+                normals.push(normal.to_array());
+                uvs.push([position.x / PLAIN_SIZE as f32, position.z / PLAIN_SIZE as f32]);
+            }
+        }
+    }
+
+    // 跨过等值面的边生成四边形, 只生成起点在区块内的边, 相邻区块不会重复
+    let mut indices: Vec<u32> = Vec::new();
+    let owned = 1..=PLAIN_SIZE as usize;
+    for x in owned.clone() {
+        for y in 1..size_y - 1 {
+            for z in owned.clone() {
+                let d0 = sample(x, y, z);
+                for axis in 0..3 {
+                    let (nx, ny, nz) = match axis {
+                        0 => (x + 1, y, z),
+                        1 => (x, y + 1, z),
+                        _ => (x, y, z + 1),
+                    };
+                    if ny >= size_y {
+                        continue;
+                    }
+                    let d1 = sample(nx, ny, nz);
+                    if (d0 > 0.0) == (d1 > 0.0) {
+                        continue;
+                    }
+                    // 围绕这条边的四个格子, 按 (b, c) 平面逆时针排列, b x c = a
+                    let quad = match axis {
+                        0 => [(x, y - 1, z - 1), (x, y, z - 1), (x, y, z), (x, y - 1, z)],
+                        1 => [(x - 1, y, z - 1), (x - 1, y, z), (x, y, z), (x, y, z - 1)],
+                        _ => [(x - 1, y - 1, z), (x, y - 1, z), (x, y, z), (x - 1, y, z)],
+                    };
+                    let v: Vec<u32> = quad
+                        .iter()
+                        .map(|(cx, cy, cz)| cell_vertex[cell_index(*cx, *cy, *cz)])
+                        .collect();
+                    if v.contains(&u32::MAX) {
+                        continue;
+                    }
+                    // 起点在实心内时, 表面朝向边的正方向
+                    if d0 > 0.0 {
+                        indices.extend([v[0], v[1], v[2], v[0], v[2], v[3]]);
+                    } else {
+                        indices.extend([v[0], v[2], v[1], v[0], v[3], v[2]]);
+                    }
+                }
+            }
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_provider::noise_graph::NoiseNode;
+    use bevy::math::Quat;
+    use bevy::render::mesh::VertexAttributeValues;
+
+    fn positions_and_normals(mesh: &Mesh) -> (Vec<Vec3>, Vec<Vec3>) {
+        let read = |attribute| match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x3(values)) => {
+                values.iter().map(|v| Vec3::from(*v)).collect()
+            }
+            _ => vec![],
+        };
+        (read(Mesh::ATTRIBUTE_POSITION), read(Mesh::ATTRIBUTE_NORMAL))
+    }
+
+    #[test]
+    fn test_surface_nets_sphere() {
+        // 采样点 (9, 9, 9) 为球心, 对应区块坐标 (8, 9, 8)
+        let size_y = SAMPLES;
+        let mut density = Vec::new();
+        for x in 0..SAMPLES {
+            for y in 0..size_y {
+                for z in 0..SAMPLES {
+                    let p = Vec3::new(x as f32, y as f32, z as f32);
+                    density.push(5.5 - p.distance(Vec3::splat(9.0)));
+                }
+            }
+        }
+        let mesh = surface_nets(&density, size_y, 0);
+        let (positions, normals) = positions_and_normals(&mesh);
+        let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+        assert!(!indices.is_empty());
+
+        // 封闭曲面: 每条有向边只出现一次, 反向边也存在
+        let mut edges = HashMap::new();
+        for tri in indices.chunks(3) {
+            for k in 0..3 {
+                *edges.entry((tri[k], tri[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        for (a, b) in edges.keys() {
+            assert_eq!(edges[&(*a, *b)], 1);
+            assert_eq!(edges.get(&(*b, *a)), Some(&1));
+        }
+
+        let center = Vec3::new(8.0, 9.0, 8.0);
+        for tri in indices.chunks(3) {
+            let [a, b, c] = [positions[tri[0]], positions[tri[1]], positions[tri[2]]];
+            let outward = (a + b + c) / 3.0 - center;
+            assert!((b - a).cross(c - a).dot(outward) > 0.0);
+        }
+        for (p, n) in positions.iter().zip(normals.iter()) {
+            assert!((p.distance(center) - 5.5).abs() < 0.5);
+            assert!(n.dot(*p - center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_empty_surface_has_no_collider() {
+        let density = vec![-1.0; SAMPLES * 4 * SAMPLES];
+        let mesh = surface_nets(&density, 4, 0);
+        assert_eq!(mesh.count_vertices(), 0);
+        assert!(surface_collider(&mesh).is_none());
+    }
+
+    #[test]
+    fn test_heights_follow_surface() {
+        let generator = SmoothGenerator::new(NormalGenerator::new(NoiseNode::Constant(3.0)));
+        let heights = generator.generate_height_area(0, 0, 2, 2);
+        assert!(heights.iter().flatten().all(|h| (h - 3.0).abs() < 1e-4));
+
+        // 悬崖上方的噪声让最高的等值面和二维高度不一样, 高度跟着网格走
+        let generator = generator.with_overhang(Overhang {
+            noise: Fractal {
+                freq: 0.05,
+                ..Fractal::default()
+            },
+            amplitude: 40.0,
+        });
+        let heights = generator.generate_height_map(0, 0, 0);
+        assert!(heights.iter().flatten().any(|h| (h - 3.0).abs() > 1.0));
+        let (collider, _) = generator.generate_collider(0, 0, 0).unwrap();
+        for (x, z_list) in heights.iter().enumerate() {
+            for (z, height) in z_list.iter().enumerate() {
+                // 从上往下打到的第一个碰撞面
+                let origin = Vec3::new(x as f32, 1000.0, z as f32);
+                let toi = collider
+                    .cast_ray(
+                        Vec3::ZERO,
+                        Quat::IDENTITY,
+                        origin,
+                        Vec3::NEG_Y,
+                        2000.0,
+                        false,
+                    )
+                    .unwrap();
+                let top = origin.y - toi;
+                assert!((height + 0.5 - top).abs() < 1.0, "{} {}", height, top);
+            }
+        }
+    }
+}
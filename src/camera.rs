@@ -0,0 +1,217 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use smooth_bevy_cameras::{LookTransform, Smoother};
+
+use crate::input::{Action, ActionState};
+use crate::player::Player;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    ThirdPerson,
+    FirstPerson,
+}
+
+// 环绕摄像机的状态
+#[derive(Resource)]
+pub struct CameraLookAt {
+    // 从摄像机指向角色的向量, 移动和投掷按它取前方
+    pub look_at: Vec3,
+    // 绕 y 轴的角度, 0 时摄像机在角色 +x 方向
+    pub yaw: f32,
+    // 俯视为正
+    pub pitch: f32,
+    pub distance: f32,
+    // 滚轮设置的目标距离, distance 平滑靠近它
+    pub target_distance: f32,
+    pub mode: CameraMode,
+    // 0 为第三人称, 1 为第一人称, 切换时平滑过渡
+    pub first_person_blend: f32,
+    // 弹簧臂当前长度, 被地形挡住时比 distance 短
+    pub arm_length: f32,
+}
+
+impl Default for CameraLookAt {
+    // 原来固定的 (20, 12, 0) 偏移
+    fn default() -> Self {
+        let offset = Vec3::new(20.0, 12.0, 0.0);
+        let mut camera = CameraLookAt {
+            look_at: -offset,
+            yaw: 0.0,
+            pitch: (offset.y / offset.x).atan(),
+            distance: offset.length(),
+            target_distance: offset.length(),
+            mode: CameraMode::ThirdPerson,
+            first_person_blend: 0.0,
+            arm_length: offset.length(),
+        };
+        camera.update_look_at();
+        camera
+    }
+}
+
+impl CameraLookAt {
+    // 摄像机看的方向
+    pub fn forward(&self) -> Vec3 {
+        -(Quat::from_rotation_y(self.yaw) * Vec3::new(self.pitch.cos(), self.pitch.sin(), 0.0))
+    }
+
+    fn update_look_at(&mut self) {
+        self.look_at = self.forward() * self.distance;
+    }
+}
+
+#[derive(Resource)]
+pub struct CameraConfig {
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // 滚轮一格缩放的比例
+    pub zoom_step: f32,
+    // 每秒靠近目标的比例, 越大越快
+    pub zoom_speed: f32,
+    pub blend_speed: f32,
+    // 第一人称眼睛相对角色中心的高度
+    pub head_height: f32,
+    // 弹簧臂探测球半径, 摄像机和障碍物至少隔开这么远
+    pub probe_radius: f32,
+    // 弹簧臂最短长度
+    pub min_arm: f32,
+    // 障碍物消失后弹簧臂伸回去的速度
+    pub arm_return_speed: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            min_pitch: -1.2,
+            max_pitch: 1.4,
+            min_distance: 4.0,
+            max_distance: 60.0,
+            zoom_step: 0.1,
+            zoom_speed: 10.0,
+            blend_speed: 6.0,
+            head_height: 0.4,
+            probe_radius: 0.4,
+            min_arm: 1.0,
+            arm_return_speed: 4.0,
+        }
+    }
+}
+
+pub fn setup(mut commands: Commands) {
+    // 平滑摄像机
+    commands.insert_resource(CameraLookAt::default());
+    commands.insert_resource(CameraConfig::default());
+    commands.spawn((
+        LookTransform::new(Vec3::ZERO, Vec3::ZERO, Vec3::Y),
+        Smoother::new(0.9),
+        Camera3d::default(),
+    ));
+}
+
+// 转动视角, 滚轮缩放, 切换第一人称
+pub fn handle_camera_input(
+    actions: Res<ActionState>,
+    config: Res<CameraConfig>,
+    time: Res<Time>,
+    mut camera_look_at: ResMut<CameraLookAt>,
+) {
+    let camera = camera_look_at.as_mut();
+    camera.yaw -= actions.look.x;
+    // 向上看时摄像机往下走
+    let max_pitch = match camera.mode {
+        CameraMode::ThirdPerson => config.max_pitch,
+        CameraMode::FirstPerson => FRAC_PI_2 - 0.01,
+    };
+    camera.pitch = (camera.pitch - actions.look.y).clamp(config.min_pitch, max_pitch);
+
+    if actions.zoom != 0.0 && camera.mode == CameraMode::ThirdPerson {
+        camera.target_distance = (camera.target_distance * (1.0 - actions.zoom * config.zoom_step))
+            .clamp(config.min_distance, config.max_distance);
+    }
+    let t = 1.0 - (-config.zoom_speed * time.delta_secs()).exp();
+    camera.distance += (camera.target_distance - camera.distance) * t;
+
+    if actions.just_pressed(Action::ToggleView) {
+        camera.mode = match camera.mode {
+            CameraMode::ThirdPerson => CameraMode::FirstPerson,
+            CameraMode::FirstPerson => CameraMode::ThirdPerson,
+        };
+    }
+    let blend_target = match camera.mode {
+        CameraMode::ThirdPerson => 0.0,
+        CameraMode::FirstPerson => 1.0,
+    };
+    let step = config.blend_speed * time.delta_secs();
+    camera.first_person_blend += (blend_target - camera.first_person_blend).clamp(-step, step);
+
+    camera.update_look_at();
+}
+
+pub fn handle_camera(
+    mut camera_look_at: ResMut<CameraLookAt>,
+    config: Res<CameraConfig>,
+    time: Res<Time>,
+    rapier_context: ReadRapierContext,
+    mut player_query: Query<(Entity, &Transform, &mut Visibility), With<Player>>,
+    mut look_transform_query: Query<(&mut LookTransform, &mut Smoother)>,
+) {
+    // 更新摄像机位置
+    let Ok((mut lt, mut smoother)) = look_transform_query.single_mut() else {
+        return;
+    };
+    let Ok((player_entity, player_position, mut visibility)) = player_query.single_mut() else {
+        return;
+    };
+    let player = player_position.translation;
+
+    // 弹簧臂: 从角色向摄像机方向投射小球, 挡住时立即收回, 之后慢慢伸出
+    let direction = -camera_look_at.forward();
+    let mut allowed = camera_look_at.distance;
+    if let Ok(context) = rapier_context.single() {
+        let filter = QueryFilter::exclude_dynamic()
+            .exclude_sensors()
+            .exclude_rigid_body(player_entity);
+        if let Some((_, hit)) = context.cast_shape(
+            player,
+            Quat::IDENTITY,
+            direction,
+            &Collider::ball(config.probe_radius),
+            ShapeCastOptions::with_max_time_of_impact(camera_look_at.distance),
+            filter,
+        ) {
+            allowed = hit.time_of_impact;
+        }
+    }
+    allowed = allowed.max(config.min_arm.min(camera_look_at.distance));
+    let blocked = allowed < camera_look_at.arm_length;
+    camera_look_at.arm_length = if blocked {
+        allowed
+    } else {
+        let t = 1.0 - (-config.arm_return_speed * time.delta_secs()).exp();
+        camera_look_at.arm_length + (allowed - camera_look_at.arm_length) * t
+    };
+
+    let third_eye = player + direction * camera_look_at.arm_length;
+    let head = player + Vec3::Y * config.head_height;
+    let first_target = head + camera_look_at.forward();
+
+    // smoothstep 过渡, 第一人称和被挡住时去掉平滑延迟, 免得穿进地形
+    let b = camera_look_at.first_person_blend;
+    let b = b * b * (3.0 - 2.0 * b);
+    lt.eye = third_eye.lerp(head, b);
+    lt.target = player.lerp(first_target, b);
+    let lag = if blocked { 0.0 } else { 0.9 * (1.0 - b) };
+    smoother.set_lag_weight(lag);
+
+    // 第一人称看不到自己
+    let hidden = camera_look_at.first_person_blend > 0.9;
+    visibility.set_if_neq(if hidden {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    });
+}
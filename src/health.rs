@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::locomotion::Grounded;
+use crate::player::{Player, SpawnPoint};
+use crate::spawn::AwaitingGround;
+use crate::water::Breath;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageSource {
+    Fall,
+    Projectile,
+    Drowning,
+    Void,
+}
+
+impl DamageSource {
+    // 持续伤害和掉出世界不受无敌时间影响
+    fn ignores_invulnerability(&self) -> bool {
+        matches!(self, DamageSource::Drowning | DamageSource::Void)
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub amount: f32,
+    pub source: DamageSource,
+}
+
+#[derive(Event, Debug)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+}
+
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    // 受伤后的无敌时间
+    pub i_frames: f32,
+    // 剩余的无敌时间
+    pub invulnerable: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new(100.0)
+    }
+}
+
+impl Health {
+    pub fn new(max: f32) -> Health {
+        Health {
+            current: max,
+            max,
+            i_frames: 0.5,
+            invulnerable: 0.0,
+        }
+    }
+
+    pub fn dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    // 返回是否真的受到了伤害
+    fn damage(&mut self, amount: f32, source: DamageSource) -> bool {
+        if self.dead() || amount <= 0.0 {
+            return false;
+        }
+        if !source.ignores_invulnerability() {
+            if self.invulnerable > 0.0 {
+                return false;
+            }
+            self.invulnerable = self.i_frames;
+        }
+        self.current = (self.current - amount).max(0.0);
+        true
+    }
+
+    fn reset(&mut self) {
+        self.current = self.max;
+        self.invulnerable = self.i_frames;
+    }
+}
+
+// 投掷物命中有 Health 的实体时造成伤害, 只算第一次命中
+#[derive(Component, Debug)]
+pub struct Projectile {
+    pub damage: f32,
+    // 扔出它的实体, 不会打到自己
+    pub owner: Entity,
+}
+
+// 已经死亡, 等待重生
+#[derive(Component, Debug)]
+pub struct Dead {
+    respawn_timer: Timer,
+}
+
+#[derive(Resource, Debug)]
+pub struct HealthConfig {
+    // 落地速度超过它开始受伤
+    pub safe_fall_speed: f32,
+    // 每超出 1 的落地速度造成的伤害
+    pub fall_damage: f32,
+    // 投掷物速度低于它不造成伤害
+    pub projectile_min_speed: f32,
+    // 没有氧气后每秒受到的伤害
+    pub drowning_damage: f32,
+    // 低于这个高度算掉出世界
+    pub void_height: f32,
+    pub respawn_delay: f32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            // 跳起来落地大约 19, 留一点余量
+            safe_fall_speed: 25.0,
+            fall_damage: 2.0,
+            projectile_min_speed: 8.0,
+            drowning_damage: 10.0,
+            // 地形高度大约在 ±200
+            void_height: -250.0,
+            respawn_delay: 3.0,
+        }
+    }
+}
+
+pub fn setup(mut commands: Commands) {
+    commands.insert_resource(HealthConfig::default());
+}
+
+pub fn fall_damage(
+    config: Res<HealthConfig>,
+    query: Query<(Entity, &Grounded), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, grounded) in query.iter() {
+        let Some(speed) = grounded.landing_velocity else {
+            continue;
+        };
+        if speed > config.safe_fall_speed {
+            damage_events.write(DamageEvent {
+                entity,
+                amount: (speed - config.safe_fall_speed) * config.fall_damage,
+                source: DamageSource::Fall,
+            });
+        }
+    }
+}
+
+pub fn projectile_damage(
+    mut commands: Commands,
+    config: Res<HealthConfig>,
+    mut collision_events: EventReader<CollisionEvent>,
+    projectile_query: Query<(&Projectile, &Velocity)>,
+    health_query: Query<(), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for event in collision_events.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        for (projectile_entity, target) in [(*a, *b), (*b, *a)] {
+            let Ok((projectile, velocity)) = projectile_query.get(projectile_entity) else {
+                continue;
+            };
+            if target == projectile.owner || !health_query.contains(target) {
+                continue;
+            }
+            if velocity.linvel.length() >= config.projectile_min_speed {
+                damage_events.write(DamageEvent {
+                    entity: target,
+                    amount: projectile.damage,
+                    source: DamageSource::Projectile,
+                });
+            }
+            commands.entity(projectile_entity).remove::<Projectile>();
+        }
+    }
+}
+
+pub fn drowning_damage(
+    config: Res<HealthConfig>,
+    time: Res<Time>,
+    query: Query<(Entity, &Breath), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, breath) in query.iter() {
+        if breath.out_of_breath() {
+            damage_events.write(DamageEvent {
+                entity,
+                amount: config.drowning_damage * time.delta_secs(),
+                source: DamageSource::Drowning,
+            });
+        }
+    }
+}
+
+pub fn void_damage(
+    config: Res<HealthConfig>,
+    query: Query<(Entity, &Transform, &Health)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, transform, health) in query.iter() {
+        if transform.translation.y < config.void_height {
+            damage_events.write(DamageEvent {
+                entity,
+                amount: health.max,
+                source: DamageSource::Void,
+            });
+        }
+    }
+}
+
+// 结算伤害, 血量归零时标记死亡
+pub fn apply_damage(
+    mut commands: Commands,
+    config: Res<HealthConfig>,
+    time: Res<Time>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut query: Query<&mut Health, Without<Dead>>,
+) {
+    for mut health in query.iter_mut() {
+        health.invulnerable = (health.invulnerable - time.delta_secs()).max(0.0);
+    }
+    for event in damage_events.read() {
+        let Ok(mut health) = query.get_mut(event.entity) else {
+            continue;
+        };
+        if !health.damage(event.amount, event.source) || !health.dead() {
+            continue;
+        }
+        println!("{:?} killed by {:?}", event.entity, event.source);
+        commands.entity(event.entity).insert((
+            Dead {
+                respawn_timer: Timer::from_seconds(config.respawn_delay, TimerMode::Once),
+            },
+            RigidBodyDisabled,
+        ));
+        death_events.write(DeathEvent {
+            entity: event.entity,
+            source: event.source,
+        });
+    }
+}
+
+// 角色死亡后在出生点重生, 其他实体直接移除
+pub fn handle_death(
+    mut commands: Commands,
+    time: Res<Time>,
+    spawn_point: Res<SpawnPoint>,
+    mut query: Query<(
+        Entity,
+        &mut Dead,
+        &mut Health,
+        &mut Transform,
+        Option<&mut Velocity>,
+        Option<&mut Breath>,
+        Has<Player>,
+    )>,
+) {
+    for (entity, mut dead, mut health, mut transform, velocity, breath, is_player) in
+        query.iter_mut()
+    {
+        if !is_player {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        dead.respawn_timer.tick(time.delta());
+        if !dead.respawn_timer.finished() {
+            continue;
+        }
+        transform.translation = spawn_point.0;
+        if let Some(mut velocity) = velocity {
+            *velocity = Velocity::zero();
+        }
+        if let Some(mut breath) = breath {
+            breath.current = breath.max;
+        }
+        health.reset();
+        // 保持冻结, 等出生点的地面加载
+        commands
+            .entity(entity)
+            .remove::<Dead>()
+            .insert(AwaitingGround);
+    }
+}
+
+// 给角色操作的系统用的运行条件
+pub fn player_alive(player_query: Query<(), (With<Player>, With<Dead>)>) -> bool {
+    player_query.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damage() {
+        let mut health = Health::new(30.0);
+        assert!(health.damage(10.0, DamageSource::Fall));
+        // 无敌时间内不受伤, 溺水不受影响
+        assert!(!health.damage(10.0, DamageSource::Projectile));
+        assert!(health.damage(5.0, DamageSource::Drowning));
+        assert_eq!(health.current, 15.0);
+
+        health.invulnerable = 0.0;
+        assert!(health.damage(100.0, DamageSource::Projectile));
+        assert!(health.dead() && health.current == 0.0);
+        assert!(!health.damage(1.0, DamageSource::Void));
+
+        health.reset();
+        assert_eq!(health.current, 30.0);
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+
+use bevy::input::gamepad::{Gamepad, GamepadButton};
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::scene::ron;
+use serde::{Deserialize, Serialize};
+
+// 随仓库发布的默认按键
+pub const DEFAULT_BINDINGS_PATH: &str = "assets/input/bindings.ron";
+// 玩家改绑后保存的按键, 不进版本库, 存在时优先读取
+pub const USER_BINDINGS_PATH: &str = "config/bindings.ron";
+
+// 玩法动作, 各系统只读动作, 不直接读按键
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sprint,
+    Crouch,
+    SlowWalk,
+    Throw,
+    Interact,
+    ToggleView,
+    ToggleFly,
+    ToggleNoclip,
+    GrabCursor,
+    ReleaseCursor,
+}
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Sprint,
+        Action::Crouch,
+        Action::SlowWalk,
+        Action::Throw,
+        Action::Interact,
+        Action::ToggleView,
+        Action::ToggleFly,
+        Action::ToggleNoclip,
+        Action::GrabCursor,
+        Action::ReleaseCursor,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButton),
+}
+
+// 摇杆的死区和响应曲线
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct StickCurve {
+    // 小于 dead_zone 的输入忽略, 大于 outer_zone 的当作推满
+    pub dead_zone: f32,
+    pub outer_zone: f32,
+    // 大于 1 时小幅推杆更细腻
+    pub exponent: f32,
+    pub sensitivity: f32,
+}
+
+impl Default for StickCurve {
+    fn default() -> Self {
+        StickCurve {
+            dead_zone: 0.15,
+            outer_zone: 0.95,
+            exponent: 1.5,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl StickCurve {
+    // 径向死区, 方向不变, 长度按曲线重新映射
+    pub fn apply(&self, stick: Vec2) -> Vec2 {
+        let length = stick.length();
+        if length <= self.dead_zone {
+            return Vec2::ZERO;
+        }
+        let t = ((length - self.dead_zone) / (self.outer_zone - self.dead_zone).max(f32::EPSILON))
+            .min(1.0);
+        stick / length * t.powf(self.exponent) * self.sensitivity
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct LookConfig {
+    // 鼠标每像素转动的弧度
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+    // 右摇杆推满时每秒转动的弧度
+    pub stick_speed: f32,
+    pub move_stick: StickCurve,
+    pub look_stick: StickCurve,
+}
+
+impl Default for LookConfig {
+    fn default() -> Self {
+        LookConfig {
+            mouse_sensitivity: 1.0 / 500.0,
+            invert_y: false,
+            stick_speed: 3.0,
+            move_stick: StickCurve::default(),
+            look_stick: StickCurve {
+                exponent: 2.0,
+                ..StickCurve::default()
+            },
+        }
+    }
+}
+
+// 最近一次有输入的设备
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputDevice {
+    #[default]
+    KeyboardMouse,
+    Gamepad,
+}
+
+// 动作到按键的映射, 保存在 RON 文件里
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InputBindings {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    #[serde(default)]
+    pub look: LookConfig,
+}
+
+impl Default for InputBindings {
+    // 原来写死的按键
+    fn default() -> Self {
+        let bindings = [
+            (Action::MoveForward, vec![Binding::Key(KeyCode::KeyW)]),
+            (Action::MoveBack, vec![Binding::Key(KeyCode::KeyS)]),
+            (Action::MoveLeft, vec![Binding::Key(KeyCode::KeyA)]),
+            (Action::MoveRight, vec![Binding::Key(KeyCode::KeyD)]),
+            (
+                Action::Jump,
+                vec![
+                    Binding::Key(KeyCode::Space),
+                    Binding::Pad(GamepadButton::South),
+                ],
+            ),
+            (
+                Action::Sprint,
+                vec![
+                    Binding::Key(KeyCode::ShiftLeft),
+                    Binding::Pad(GamepadButton::LeftThumb),
+                ],
+            ),
+            (
+                Action::Crouch,
+                vec![
+                    Binding::Key(KeyCode::ControlLeft),
+                    Binding::Pad(GamepadButton::East),
+                ],
+            ),
+            (Action::SlowWalk, vec![Binding::Key(KeyCode::AltLeft)]),
+            (
+                Action::Throw,
+                vec![
+                    Binding::Mouse(MouseButton::Left),
+                    Binding::Pad(GamepadButton::RightTrigger2),
+                    Binding::Pad(GamepadButton::LeftTrigger2),
+                ],
+            ),
+            (
+                Action::Interact,
+                vec![
+                    Binding::Key(KeyCode::KeyE),
+                    Binding::Pad(GamepadButton::West),
+                ],
+            ),
+            (
+                Action::ToggleView,
+                vec![
+                    Binding::Key(KeyCode::KeyV),
+                    Binding::Pad(GamepadButton::North),
+                ],
+            ),
+            (Action::ToggleFly, vec![Binding::Key(KeyCode::KeyF)]),
+            (Action::ToggleNoclip, vec![Binding::Key(KeyCode::KeyN)]),
+            (Action::GrabCursor, vec![Binding::Mouse(MouseButton::Left)]),
+            (Action::ReleaseCursor, vec![Binding::Key(KeyCode::Escape)]),
+        ];
+        InputBindings {
+            bindings: bindings.into_iter().collect(),
+            look: LookConfig::default(),
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn load(path: &str) -> Result<InputBindings, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let mut bindings: InputBindings = ron::de::from_str(&text)?;
+        // 旧文件里没有的动作用默认按键
+        for (action, list) in InputBindings::default().bindings {
+            bindings.bindings.entry(action).or_insert(list);
+        }
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn bindings_for(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    // 把 action 改绑到 binding, 其他动作上的同一个按键解绑
+    // (GrabCursor 这种界面动作和玩法动作可以共用按键, 不受影响)
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        if !matches!(action, Action::GrabCursor | Action::ReleaseCursor) {
+            for (other, list) in self.bindings.iter_mut() {
+                if !matches!(other, Action::GrabCursor | Action::ReleaseCursor) {
+                    list.retain(|b| *b != binding);
+                }
+            }
+        }
+        // 只替换同一类设备的按键, 键鼠改绑不影响手柄
+        let is_pad = |b: &Binding| matches!(b, Binding::Pad(_));
+        let list = self.bindings.entry(action).or_default();
+        list.retain(|b| is_pad(b) != is_pad(&binding));
+        list.push(binding);
+    }
+}
+
+// 当前帧的动作状态
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    // x 向右, y 向前, 长度不超过 1
+    pub movement: Vec2,
+    // 这一帧视角转动的弧度, x 向右, y 向上
+    pub look: Vec2,
+    // 这一帧的缩放量, 正数拉近, 滚轮一格为 1
+    pub zoom: f32,
+    pub device: InputDevice,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+}
+
+// 等待改绑的动作, 下一个按下的键, 鼠标或手柄按钮会绑定上去
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    pub action: Option<Action>,
+}
+
+// 先读玩家的按键, 没有或读取失败时读默认按键, 都失败时用内置的默认值
+fn load_bindings() -> InputBindings {
+    let mut paths = vec![DEFAULT_BINDINGS_PATH];
+    if std::path::Path::new(USER_BINDINGS_PATH).exists() {
+        paths.insert(0, USER_BINDINGS_PATH);
+    }
+    for path in paths {
+        match InputBindings::load(path) {
+            Ok(bindings) => return bindings,
+            Err(err) => println!("load {path} failed: {err}"),
+        }
+    }
+    println!("use default bindings");
+    InputBindings::default()
+}
+
+pub fn startup(mut commands: Commands) {
+    let bindings = load_bindings();
+    commands.insert_resource(bindings);
+    commands.insert_resource(ActionState::default());
+    commands.insert_resource(Rebinding::default());
+}
+
+// 键鼠和手柄 -> 动作, 在 PreUpdate 里输入更新之后运行
+pub fn update_actions(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    gamepads: Query<&Gamepad>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    time: Res<Time>,
+    mut actions: ResMut<ActionState>,
+) {
+    let pad_pressed = |button: GamepadButton| gamepads.iter().any(|g| g.pressed(button));
+    let pad_just_pressed = |button: GamepadButton| gamepads.iter().any(|g| g.just_pressed(button));
+    let pad_just_released =
+        |button: GamepadButton| gamepads.iter().any(|g| g.just_released(button));
+    let pressed = |b: &Binding| match b {
+        Binding::Key(key) => keyboard.pressed(*key),
+        Binding::Mouse(button) => mouse.pressed(*button),
+        Binding::Pad(button) => pad_pressed(*button),
+    };
+    let just_pressed = |b: &Binding| match b {
+        Binding::Key(key) => keyboard.just_pressed(*key),
+        Binding::Mouse(button) => mouse.just_pressed(*button),
+        Binding::Pad(button) => pad_just_pressed(*button),
+    };
+    let just_released = |b: &Binding| match b {
+        Binding::Key(key) => keyboard.just_released(*key),
+        Binding::Mouse(button) => mouse.just_released(*button),
+        Binding::Pad(button) => pad_just_released(*button),
+    };
+
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    actions.just_released.clear();
+    // 改绑时按下的键不触发动作
+    if rebinding.action.is_none() {
+        for action in Action::ALL {
+            let list = bindings.bindings_for(action);
+            if list.iter().any(pressed) {
+                actions.pressed.insert(action);
+            }
+            if list.iter().any(just_pressed) {
+                actions.just_pressed.insert(action);
+            }
+            if list.iter().any(just_released) {
+                actions.just_released.insert(action);
+            }
+        }
+    }
+
+    let config = &bindings.look;
+    let axis = |positive: Action, negative: Action| {
+        actions.pressed(positive) as i32 as f32 - actions.pressed(negative) as i32 as f32
+    };
+    let keys_movement = Vec2::new(
+        axis(Action::MoveRight, Action::MoveLeft),
+        axis(Action::MoveForward, Action::MoveBack),
+    )
+    .normalize_or_zero();
+    let mouse_look = mouse_motion_events
+        .read()
+        .fold(Vec2::ZERO, |acc, motion| acc + motion.delta)
+        * config.mouse_sensitivity
+        * Vec2::new(1.0, -1.0);
+
+    let wheel = mouse_wheel_events
+        .read()
+        .map(|wheel| match wheel.unit {
+            MouseScrollUnit::Line => wheel.y,
+            MouseScrollUnit::Pixel => wheel.y / 100.0,
+        })
+        .sum::<f32>();
+
+    // 取推得最远的手柄
+    let (pad_movement, pad_look) = if rebinding.action.is_none() {
+        gamepads.iter().fold((Vec2::ZERO, Vec2::ZERO), |(m, l), g| {
+            let movement = config.move_stick.apply(g.left_stick());
+            let look = config.look_stick.apply(g.right_stick());
+            (
+                if movement.length() > m.length() {
+                    movement
+                } else {
+                    m
+                },
+                if look.length() > l.length() { look } else { l },
+            )
+        })
+    } else {
+        (Vec2::ZERO, Vec2::ZERO)
+    };
+    let pad_look = pad_look * config.stick_speed * time.delta_secs();
+
+    // 哪个设备有输入就切到哪个, 两边同时有输入时键鼠优先
+    let keyboard_active = keys_movement != Vec2::ZERO
+        || mouse_look != Vec2::ZERO
+        || wheel != 0.0
+        || keyboard.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some();
+    let pad_active = pad_movement != Vec2::ZERO
+        || pad_look != Vec2::ZERO
+        || gamepads
+            .iter()
+            .any(|g| g.get_just_pressed().next().is_some());
+    if keyboard_active {
+        actions.device = InputDevice::KeyboardMouse;
+    } else if pad_active {
+        actions.device = InputDevice::Gamepad;
+    }
+
+    actions.movement = if keys_movement != Vec2::ZERO {
+        keys_movement
+    } else {
+        pad_movement.clamp_length_max(1.0)
+    };
+    // 十字键上下每秒缩放 5 格
+    let pad_zoom =
+        gamepads
+            .iter()
+            .map(|g| g.dpad().y)
+            .fold(0.0, |a: f32, b| if b.abs() > a.abs() { b } else { a });
+    actions.zoom = wheel + pad_zoom * 5.0 * time.delta_secs();
+
+    let mut look = mouse_look + pad_look;
+    if config.invert_y {
+        look.y = -look.y;
+    }
+    actions.look = look;
+}
+
+// F10 依次改绑每个动作, 全部改完后保存到玩家的按键文件
+pub fn rebind_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some(action) = rebinding.action else {
+        if keyboard.just_pressed(KeyCode::F10) {
+            rebinding.action = Some(Action::ALL[0]);
+            println!("rebind {:?}: press a key or button", Action::ALL[0]);
+        }
+        return;
+    };
+
+    let binding = keyboard
+        .get_just_pressed()
+        .find(|key| **key != KeyCode::F10)
+        .map(|key| Binding::Key(*key))
+        .or_else(|| mouse.get_just_pressed().next().map(|b| Binding::Mouse(*b)))
+        .or_else(|| {
+            gamepads
+                .iter()
+                .find_map(|g| g.get_just_pressed().next().map(|b| Binding::Pad(*b)))
+        });
+    if keyboard.just_pressed(KeyCode::F10) {
+        // 再按 F10 跳过当前动作
+    } else if let Some(binding) = binding {
+        bindings.rebind(action, binding);
+        println!("{action:?} -> {binding:?}");
+    } else {
+        return;
+    }
+
+    let next = Action::ALL.iter().position(|a| *a == action).unwrap() + 1;
+    rebinding.action = Action::ALL.get(next).copied();
+    match rebinding.action {
+        Some(next) => println!("rebind {next:?}: press a key or button"),
+        None => match bindings.save(USER_BINDINGS_PATH) {
+            Ok(()) => println!("bindings saved to {USER_BINDINGS_PATH}"),
+            Err(err) => println!("save {USER_BINDINGS_PATH} failed: {err}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings() {
+        let mut bindings = InputBindings::default();
+        let text = bindings.to_ron().unwrap();
+        let parsed: InputBindings = ron::de::from_str(&text).unwrap();
+        assert_eq!(parsed, bindings);
+        // 随仓库发布的默认按键文件和内置默认值一致
+        assert_eq!(
+            InputBindings::load(DEFAULT_BINDINGS_PATH).unwrap(),
+            bindings
+        );
+
+        // 改绑会从其他玩法动作上移除同一个键, 界面动作保留
+        bindings.rebind(Action::Jump, Binding::Key(KeyCode::KeyW));
+        assert_eq!(
+            bindings.bindings_for(Action::Jump),
+            &[
+                Binding::Pad(GamepadButton::South),
+                Binding::Key(KeyCode::KeyW)
+            ]
+        );
+        assert!(bindings.bindings_for(Action::MoveForward).is_empty());
+        bindings.rebind(Action::Interact, Binding::Mouse(MouseButton::Left));
+        assert!(!bindings
+            .bindings_for(Action::Throw)
+            .contains(&Binding::Mouse(MouseButton::Left)));
+        assert_eq!(
+            bindings.bindings_for(Action::GrabCursor),
+            &[Binding::Mouse(MouseButton::Left)]
+        );
+    }
+
+    #[test]
+    fn test_stick_curve() {
+        let curve = StickCurve::default();
+        assert_eq!(curve.apply(Vec2::new(0.1, 0.1)), Vec2::ZERO);
+        // 推满以及超出外圈都是 1, 方向不变
+        let full = curve.apply(Vec2::new(0.0, -1.0));
+        assert!((full - Vec2::NEG_Y).length() < 1e-6);
+        // 刚过死区是 0 附近, 曲线单调
+        let mut last = 0.0;
+        for i in 16..=100 {
+            let v = curve.apply(Vec2::X * i as f32 / 100.0).x;
+            assert!(v >= last && v <= 1.0);
+            last = v;
+        }
+        assert!(curve.apply(Vec2::X * 0.16).x < 0.01);
+    }
+}
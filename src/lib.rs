@@ -1,7 +1,15 @@
+pub mod camera;
 pub mod cubePlain;
 pub mod customMaterial;
+pub mod health;
+pub mod input;
+pub mod locomotion;
 pub mod player;
 pub mod npc;
 pub mod region;
+pub mod spawn;
+pub mod spectator;
+pub mod structure;
 pub mod util;
+pub mod water;
 pub mod block_provider;
@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::{Action, ActionState};
+use crate::player::Player;
+use crate::water::Swimming;
+
+// rapier 默认重力
+const GRAVITY: f32 = 9.81;
+
+// 着地检测的结果
+#[derive(Component, Default, Debug)]
+pub struct Grounded {
+    pub grounded: bool,
+    // 离开地面多久了, 着地时为 0
+    pub time_since_grounded: f32,
+    // 着地那一帧的下落速度 (正数), 其他帧为 None
+    pub landing_velocity: Option<f32>,
+    last_vertical_velocity: f32,
+}
+
+// 跳跃和重力参数
+#[derive(Component, Clone, Debug)]
+pub struct GravityProfile {
+    // 按住跳跃键能跳的高度
+    pub jump_height: f32,
+    // 上升时的重力倍数
+    pub rise_scale: f32,
+    // 上升中松开跳跃键时重力再乘这个倍数, 跳得更低
+    pub jump_cut_scale: f32,
+    // 下落时的重力倍数, 比上升大落得更干脆
+    pub fall_scale: f32,
+    pub max_fall_speed: f32,
+    // 离开地面后这段时间内还能起跳
+    pub coyote_time: f32,
+    // 落地前这段时间内按下的跳跃会在落地时生效
+    pub jump_buffer: f32,
+}
+
+impl Default for GravityProfile {
+    fn default() -> Self {
+        GravityProfile {
+            jump_height: 3.0,
+            rise_scale: 4.0,
+            jump_cut_scale: 3.0,
+            fall_scale: 6.0,
+            max_fall_speed: 60.0,
+            coyote_time: 0.12,
+            jump_buffer: 0.15,
+        }
+    }
+}
+
+// 调试用的飞行模式, 没有重力, 跳跃键上升, 蹲下键下降
+#[derive(Component, Default, Debug)]
+pub struct Flying;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JumpPhase {
+    #[default]
+    Grounded,
+    Rising,
+    Falling,
+}
+
+#[derive(Component, Default, Debug)]
+pub struct JumpState {
+    pub phase: JumpPhase,
+    // 距离上次按下跳跃的时间, None 表示没有待处理的跳跃
+    buffered: Option<f32>,
+    // 这次离地是不是跳起来的, 跳起来的不再给土狼时间
+    jumped: bool,
+}
+
+// 向下投射一个比碰撞体略小的球判断是否站在地上
+pub fn update_grounded(
+    rapier_context: ReadRapierContext,
+    time: Res<Time>,
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &Velocity,
+        Option<&KinematicCharacterControllerOutput>,
+        &mut Grounded,
+    )>,
+) {
+    let Ok(context) = rapier_context.single() else {
+        return;
+    };
+    for (entity, transform, collider, velocity, output, mut grounded) in query.iter_mut() {
+        let half: Vec3 = collider.raw.compute_local_aabb().half_extents().into();
+        let radius = half.x.min(half.z) * 0.9;
+        // 球心到碰撞体底部的距离, 再留 0.15 给控制器的 offset
+        let max_distance = half.y - radius + 0.15;
+        let hit = context
+            .cast_shape(
+                transform.translation,
+                Quat::IDENTITY,
+                Vec3::NEG_Y,
+                &Collider::ball(radius),
+                ShapeCastOptions::with_max_time_of_impact(max_distance),
+                QueryFilter::exclude_dynamic()
+                    .exclude_sensors()
+                    .exclude_rigid_body(entity),
+            )
+            .is_some();
+        let on_ground = (hit || output.is_some_and(|o| o.grounded)) && velocity.linvel.y < 0.5;
+
+        grounded.landing_velocity = if on_ground && !grounded.grounded {
+            Some(-grounded.last_vertical_velocity.min(0.0))
+        } else {
+            None
+        };
+        grounded.grounded = on_ground;
+        grounded.time_since_grounded = if on_ground {
+            0.0
+        } else {
+            grounded.time_since_grounded + time.delta_secs()
+        };
+        grounded.last_vertical_velocity = velocity.linvel.y;
+    }
+}
+
+// 跳跃状态机: 跳跃缓冲, 土狼时间, 松开跳跃键提前下落
+pub fn handle_jump(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    mut query: Query<
+        (
+            &Grounded,
+            &GravityProfile,
+            &mut JumpState,
+            &mut Velocity,
+            &mut GravityScale,
+        ),
+        (With<Player>, Without<Flying>, Without<Swimming>),
+    >,
+) {
+    for (grounded, profile, mut jump, mut velocity, mut gravity_scale) in query.iter_mut() {
+        if actions.just_pressed(Action::Jump) {
+            jump.buffered = Some(0.0);
+        } else if let Some(t) = jump.buffered {
+            let t = t + time.delta_secs();
+            jump.buffered = (t <= profile.jump_buffer).then_some(t);
+        }
+        if grounded.grounded {
+            jump.jumped = false;
+        }
+
+        let can_jump = grounded.grounded
+            || (!jump.jumped && grounded.time_since_grounded <= profile.coyote_time);
+        if jump.buffered.is_some() && can_jump {
+            // v = sqrt(2gh)
+            let g = GRAVITY * profile.rise_scale;
+            velocity.linvel.y = (2.0 * g * profile.jump_height).sqrt();
+            jump.buffered = None;
+            jump.jumped = true;
+            jump.phase = JumpPhase::Rising;
+        } else if grounded.grounded {
+            jump.phase = JumpPhase::Grounded;
+        } else if velocity.linvel.y > 0.0 {
+            jump.phase = JumpPhase::Rising;
+        } else {
+            jump.phase = JumpPhase::Falling;
+        }
+
+        gravity_scale.0 = match jump.phase {
+            JumpPhase::Grounded => profile.rise_scale,
+            JumpPhase::Rising if jump.jumped && !actions.pressed(Action::Jump) => {
+                profile.rise_scale * profile.jump_cut_scale
+            }
+            JumpPhase::Rising => profile.rise_scale,
+            JumpPhase::Falling => profile.fall_scale,
+        };
+        velocity.linvel.y = velocity.linvel.y.max(-profile.max_fall_speed);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MovementState {
+    #[default]
+    Idle,
+    Walk,
+    SlowWalk,
+    Sprint,
+    Crouch,
+    Airborne,
+    Fly,
+    Swim,
+}
+
+#[derive(Component, Default)]
+pub struct Movement {
+    // 想要移动的水平方向 (世界坐标), 长度不超过 1
+    pub input: Vec3,
+    pub state: MovementState,
+    // 当前水平速度
+    pub velocity: Vec3,
+    // 蹲下时保存站立的碰撞体
+    standing: Option<Collider>,
+}
+
+impl Movement {
+    pub fn crouching(&self) -> bool {
+        self.standing.is_some()
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct MovementConfig {
+    pub walk_speed: f32,
+    pub slow_walk_speed: f32,
+    pub sprint_speed: f32,
+    pub crouch_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    // (当前速度 / 目标速度, 加速度倍数) 控制点, 中间线性插值
+    pub acceleration_curve: Vec<(f32, f32)>,
+    // 空中加减速的倍数
+    pub air_control: f32,
+    // 蹲下时碰撞体的高度
+    pub crouch_height: f32,
+    pub fly_speed: f32,
+    pub fly_sprint_speed: f32,
+    pub swim_speed: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            walk_speed: 20.0,
+            slow_walk_speed: 6.0,
+            sprint_speed: 32.0,
+            crouch_speed: 8.0,
+            acceleration: 120.0,
+            deceleration: 160.0,
+            // 起步快, 接近目标速度时变缓
+            acceleration_curve: vec![(0.0, 1.5), (0.7, 1.0), (1.0, 0.4)],
+            air_control: 0.3,
+            crouch_height: 0.6,
+            fly_speed: 30.0,
+            fly_sprint_speed: 90.0,
+            swim_speed: 10.0,
+        }
+    }
+}
+
+impl MovementConfig {
+    fn acceleration_at(&self, ratio: f32) -> f32 {
+        let curve = &self.acceleration_curve;
+        let scale = match curve.iter().position(|(x, _)| *x > ratio) {
+            None => curve.last().map_or(1.0, |(_, y)| *y),
+            Some(0) => curve[0].1,
+            Some(i) => {
+                let ((x0, y0), (x1, y1)) = (curve[i - 1], curve[i]);
+                y0 + (y1 - y0) * (ratio - x0) / (x1 - x0)
+            }
+        };
+        self.acceleration * scale
+    }
+
+    fn speed(&self, state: MovementState) -> f32 {
+        match state {
+            MovementState::Idle => 0.0,
+            MovementState::Walk | MovementState::Airborne => self.walk_speed,
+            MovementState::SlowWalk => self.slow_walk_speed,
+            MovementState::Sprint => self.sprint_speed,
+            MovementState::Crouch => self.crouch_speed,
+            MovementState::Fly => self.fly_speed,
+            MovementState::Swim => self.swim_speed,
+        }
+    }
+}
+
+// 冲刺消耗的体力
+#[derive(Component, Debug)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    // 每秒消耗 / 恢复
+    pub drain: f32,
+    pub regen: f32,
+    // 停止冲刺后多久开始恢复
+    pub regen_delay: f32,
+    // 耗尽后要恢复到这么多才能再冲刺
+    pub min_to_sprint: f32,
+    pub exhausted: bool,
+    since_used: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina {
+            current: 100.0,
+            max: 100.0,
+            drain: 25.0,
+            regen: 20.0,
+            regen_delay: 1.0,
+            min_to_sprint: 25.0,
+            exhausted: false,
+            since_used: 0.0,
+        }
+    }
+}
+
+impl Stamina {
+    fn update(&mut self, sprinting: bool, dt: f32) {
+        if sprinting {
+            self.current = (self.current - self.drain * dt).max(0.0);
+            self.since_used = 0.0;
+            if self.current <= 0.0 {
+                self.exhausted = true;
+            }
+        } else {
+            self.since_used += dt;
+            if self.since_used >= self.regen_delay {
+                self.current = (self.current + self.regen * dt).min(self.max);
+            }
+            if self.current >= self.min_to_sprint {
+                self.exhausted = false;
+            }
+        }
+    }
+}
+
+// 行走, 冲刺, 蹲下, 慢走; 蹲下时不会从边缘掉下去
+pub fn update_movement(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    rapier_context: ReadRapierContext,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &mut Collider,
+        &mut Movement,
+        &MovementConfig,
+        &Grounded,
+        Option<&mut Stamina>,
+        &mut KinematicCharacterController,
+        Has<Flying>,
+        Has<Swimming>,
+    )>,
+) {
+    let Ok(context) = rapier_context.single() else {
+        return;
+    };
+    let dt = time.delta_secs();
+    for (
+        entity,
+        mut transform,
+        mut collider,
+        mut movement,
+        config,
+        grounded,
+        mut stamina,
+        mut controller,
+        flying,
+        swimming,
+    ) in query.iter_mut()
+    {
+        let filter = QueryFilter::exclude_dynamic()
+            .exclude_sensors()
+            .exclude_rigid_body(entity);
+
+        // 蹲下换成矮一些的圆柱, 脚底位置不变; 飞行和游泳时蹲下键用来下降
+        let can_crouch = grounded.grounded && !flying && !swimming;
+        if actions.pressed(Action::Crouch) && !movement.crouching() && can_crouch {
+            let half: Vec3 = collider.raw.compute_local_aabb().half_extents().into();
+            let crouch_half = (config.crouch_height * 0.5).min(half.y);
+            let crouched = Collider::cylinder(crouch_half, half.x.min(half.z));
+            movement.standing = Some(std::mem::replace(collider.as_mut(), crouched));
+            transform.translation.y -= half.y - crouch_half;
+        } else if !actions.pressed(Action::Crouch) && movement.crouching() {
+            // 头顶有东西时继续蹲着
+            let standing = movement.standing.clone().unwrap();
+            let stand_half = standing.raw.compute_local_aabb().half_extents().y;
+            let crouch_half = collider.raw.compute_local_aabb().half_extents().y;
+            let center = transform.translation + Vec3::Y * (stand_half - crouch_half);
+            let mut blocked = false;
+            context.intersections_with_shape(center, Quat::IDENTITY, &standing, filter, |_| {
+                blocked = true;
+                false
+            });
+            if !blocked {
+                *collider = standing;
+                movement.standing = None;
+                transform.translation = center;
+            }
+        }
+
+        let moving = movement.input.length() > 0.1;
+        let wants_sprint =
+            actions.pressed(Action::Sprint) && moving && !movement.crouching() && !swimming;
+        let can_sprint = stamina.as_ref().is_none_or(|s| !s.exhausted);
+        // 飞行时冲刺不消耗体力
+        let sprinting = wants_sprint && (flying || can_sprint && grounded.grounded);
+        if let Some(stamina) = stamina.as_mut() {
+            stamina.update(sprinting && !flying, dt);
+        }
+
+        movement.state = if flying {
+            MovementState::Fly
+        } else if swimming {
+            MovementState::Swim
+        } else if !grounded.grounded {
+            MovementState::Airborne
+        } else if !moving {
+            MovementState::Idle
+        } else if movement.crouching() {
+            MovementState::Crouch
+        } else if sprinting {
+            MovementState::Sprint
+        } else if actions.pressed(Action::SlowWalk) {
+            MovementState::SlowWalk
+        } else {
+            MovementState::Walk
+        };
+
+        // 空中保持起跳时的速度, 只能少量调整方向
+        let mut target_speed = config.speed(movement.state);
+        let mut control = 1.0;
+        if movement.state == MovementState::Airborne {
+            target_speed = target_speed.max(movement.velocity.length());
+            control = config.air_control;
+        }
+        if flying && sprinting {
+            target_speed = config.fly_sprint_speed;
+        }
+        let target = movement.input.clamp_length_max(1.0) * target_speed;
+        let delta = target - movement.velocity;
+        let rate = if target.length() > movement.velocity.length() {
+            let ratio = movement.velocity.length() / target_speed.max(f32::EPSILON);
+            config.acceleration_at(ratio)
+        } else {
+            config.deceleration
+        };
+        movement.velocity += delta.clamp_length_max(rate * control * dt);
+
+        let mut step = movement.velocity * dt;
+        if movement.crouching() && grounded.grounded && step != Vec3::ZERO {
+            // 前方脚下没有地面就不走, 先试整步, 再分别试 x 和 z 沿边缘滑动
+            let half: Vec3 = collider.raw.compute_local_aabb().half_extents().into();
+            let has_ground = |step: Vec3| {
+                let ahead = step.normalize_or_zero() * half.x.min(half.z);
+                context
+                    .cast_ray(
+                        transform.translation + step + ahead,
+                        Vec3::NEG_Y,
+                        half.y + 0.6,
+                        true,
+                        filter,
+                    )
+                    .is_some()
+            };
+            step = [
+                step,
+                Vec3::new(step.x, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, step.z),
+            ]
+            .into_iter()
+            .find(|s| *s != Vec3::ZERO && has_ground(*s))
+            .unwrap_or(Vec3::ZERO);
+            movement.velocity = step / dt.max(f32::EPSILON);
+        }
+        if flying {
+            let vertical =
+                actions.pressed(Action::Jump) as i32 - actions.pressed(Action::Crouch) as i32;
+            step.y = vertical as f32 * target_speed * dt;
+        }
+        controller.translation = Some(step);
+    }
+}
+
+// 按移动状态播放动画, 动画速度跟着实际移动速度
+#[derive(Component)]
+pub struct AnimationDriver {
+    // 每个状态的动画节点, 以及动画原速时对应的移动速度 (0 表示不调速度)
+    pub clips: HashMap<MovementState, (AnimationNodeIndex, f32)>,
+    pub graph: Handle<AnimationGraph>,
+    pub transition: Duration,
+    current: Option<AnimationNodeIndex>,
+}
+
+impl AnimationDriver {
+    pub fn new(
+        graphs: &mut Assets<AnimationGraph>,
+        clips: Vec<(MovementState, Handle<AnimationClip>, f32)>,
+    ) -> AnimationDriver {
+        let (graph, nodes) =
+            AnimationGraph::from_clips(clips.iter().map(|(_, clip, _)| clip.clone()));
+        AnimationDriver {
+            clips: clips
+                .iter()
+                .zip(nodes)
+                .map(|((state, _, speed), node)| (*state, (node, *speed)))
+                .collect(),
+            graph: graphs.add(graph),
+            transition: Duration::from_millis(200),
+            current: None,
+        }
+    }
+}
+
+pub fn drive_animation(
+    mut commands: Commands,
+    mut drivers: Query<(Entity, &Movement, &mut AnimationDriver)>,
+    children: Query<&Children>,
+    mut players: Query<(&mut AnimationPlayer, Option<&mut AnimationTransitions>)>,
+) {
+    for (entity, movement, mut driver) in drivers.iter_mut() {
+        // 模型加载后 AnimationPlayer 出现在子实体里
+        let Some(player_entity) = children
+            .iter_descendants(entity)
+            .find(|e| players.contains(*e))
+        else {
+            continue;
+        };
+        let Ok((mut player, transitions)) = players.get_mut(player_entity) else {
+            continue;
+        };
+        let Some(mut transitions) = transitions else {
+            commands.entity(player_entity).insert((
+                AnimationGraphHandle(driver.graph.clone()),
+                AnimationTransitions::new(),
+            ));
+            driver.current = None;
+            continue;
+        };
+
+        // 没有对应动画的状态用走路或待机
+        let Some((node, base_speed)) = [movement.state, MovementState::Walk, MovementState::Idle]
+            .iter()
+            .find_map(|state| driver.clips.get(state).copied())
+        else {
+            continue;
+        };
+        if driver.current != Some(node) {
+            transitions
+                .play(&mut player, node, driver.transition)
+                .repeat();
+            driver.current = Some(node);
+        }
+        if base_speed > 0.0 {
+            let speed = (movement.velocity.length() / base_speed).max(0.1);
+            if let Some(animation) = player.animation_mut(node) {
+                animation.set_speed(speed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acceleration_curve() {
+        let config = MovementConfig::default();
+        assert_eq!(config.acceleration_at(0.0), 120.0 * 1.5);
+        assert!((config.acceleration_at(0.35) - 120.0 * 1.25).abs() < 1e-3);
+        assert_eq!(config.acceleration_at(2.0), 120.0 * 0.4);
+    }
+
+    #[test]
+    fn test_stamina() {
+        let mut stamina = Stamina::default();
+        // 4 秒冲刺耗尽
+        for _ in 0..41 {
+            stamina.update(true, 0.1);
+        }
+        assert!(stamina.exhausted && stamina.current == 0.0);
+        // 延迟 1 秒后开始恢复, 恢复到 25 才能再冲刺
+        for _ in 0..20 {
+            stamina.update(false, 0.1);
+        }
+        assert!(stamina.exhausted);
+        for _ in 0..5 {
+            stamina.update(false, 0.1);
+        }
+        assert!(!stamina.exhausted);
+    }
+}
@@ -1,9 +1,14 @@
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::input::InputSystem;
 use bevy::prelude::*;
 use bevy::scene::ron::de;
 use bevy::window::{CursorGrabMode, CursorOptions};
 use bevy_rapier3d::prelude::*;
-use cube_world::{block_provider, cubePlain, customMaterial, npc, player, region};
+use cube_world::input::{Action, ActionState};
+use cube_world::{
+    block_provider, camera, cubePlain, customMaterial, health, input, locomotion, npc, player,
+    region, spawn, spectator, structure, water,
+};
 use smooth_bevy_cameras::LookTransformPlugin;
 
 fn main() {
@@ -20,38 +25,93 @@ fn main() {
         .add_systems(
             Startup,
             (
+                input::startup,
+                camera::setup,
                 player::setup,
                 npc::setup,
                 region::startup,
                 block_provider::setup,
+                structure::setup,
+                water::setup,
+                health::setup,
+                spawn::setup
+                    .after(block_provider::setup)
+                    .after(player::setup)
+                    .after(npc::setup)
+                    .after(water::setup),
             ),
         )
+        .init_resource::<block_provider::MapGeneratorName>()
+        .init_resource::<region::RegionRenderMode>()
+        .init_resource::<spawn::SpawnConfig>()
+        .add_event::<health::DamageEvent>()
+        .add_event::<health::DeathEvent>()
+        .add_systems(PreUpdate, input::update_actions.after(InputSystem))
         .add_systems(
             Update,
             (
-                player::handle_keyboard_controls,
-                player::handle_mouse_motion,
-                player::handle_camera,
+                input::rebind_input,
+                (
+                    water::update_submersion,
+                    water::handle_swimming,
+                    water::apply_buoyancy,
+                    water::update_breath,
+                )
+                    .chain(),
+                (
+                    locomotion::update_grounded,
+                    locomotion::handle_jump,
+                    player::handle_keyboard_controls,
+                    locomotion::update_movement,
+                )
+                    .chain()
+                    .run_if(spectator::not_spectating)
+                    .run_if(health::player_alive)
+                    .run_if(spawn::player_ready),
+                locomotion::drive_animation,
+                player::handle_mouse_motion
+                    .run_if(spectator::not_spectating)
+                    .run_if(health::player_alive)
+                    .run_if(spawn::player_ready),
+                (
+                    camera::handle_camera_input,
+                    spectator::toggle_fly,
+                    spectator::toggle_noclip,
+                    spectator::move_spectator,
+                    spectator::spectator_camera,
+                    camera::handle_camera.run_if(spectator::not_spectating),
+                )
+                    .chain(),
+                (
+                    health::fall_damage,
+                    health::projectile_damage,
+                    health::drowning_damage,
+                    health::void_damage,
+                    health::apply_damage,
+                    health::handle_death,
+                )
+                    .chain(),
                 player::del_bullet,
                 npc::handle_keyboard_controls,
                 region::region_update,
+                spawn::release_on_ground,
+                water::follow_anchor,
+                water::underwater_camera,
+                region::region_export,
+                structure::structure_update,
                 grab_mouse,
             ),
         )
         .run();
 }
 
-fn grab_mouse(
-    mut window: Single<&mut Window>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    key: Res<ButtonInput<KeyCode>>,
-) {
-    if mouse.just_pressed(MouseButton::Left) {
+fn grab_mouse(mut window: Single<&mut Window>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::GrabCursor) {
         window.cursor_options.visible = false;
         window.cursor_options.grab_mode = CursorGrabMode::Locked;
     }
 
-    if key.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(Action::ReleaseCursor) {
         window.cursor_options.visible = true;
         window.cursor_options.grab_mode = CursorGrabMode::None;
     }
@@ -10,6 +10,9 @@ use bevy_rapier3d::prelude::*;
 use rand::Rng;
 use smooth_bevy_cameras::{LookTransform, Smoother};
 
+use crate::health::Health;
+use crate::water::{Submersion, SwimConfig};
+
 #[derive(Component)]
 pub struct Npc {
     towards: Vec3,
@@ -52,6 +55,9 @@ pub fn setup(
         },
         Ccd::enabled(),
         Velocity::zero(),
+        Submersion::default(),
+        SwimConfig::default(),
+        Health::default(),
         // CollisionGroups::new(collider_player, collider_ground),
     ));
 
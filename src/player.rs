@@ -1,20 +1,33 @@
 use std::f32::consts::PI;
 
 use bevy::gltf::{Gltf, GltfMesh, GltfNode};
-use bevy::input::mouse::MouseMotion;
 use bevy::math::VectorSpace;
 use bevy::prelude::*;
 use bevy::render::render_resource::Texture;
 use bevy::state::commands;
 use bevy_rapier3d::prelude::*;
-use smooth_bevy_cameras::{LookTransform, Smoother};
+
+use crate::camera::CameraLookAt;
+use crate::health::{Health, Projectile};
+use crate::input::{Action, ActionState};
+use crate::locomotion::{
+    AnimationDriver, GravityProfile, Grounded, JumpState, Movement, MovementConfig,
+    MovementState, Stamina,
+};
+use crate::region::StreamingAnchor;
+use crate::water::{Breath, Buoyancy, Submersion, SwimConfig};
 
 #[derive(Component)]
 pub struct Player;
 
-#[derive(Resource)]
-pub struct CameraLookAt {
-    pub look_at: Vec3,
+// 出生和重生的位置
+#[derive(Resource, Debug)]
+pub struct SpawnPoint(pub Vec3);
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        SpawnPoint(Vec3::new(0.0, 10.0, 0.0))
+    }
 }
 
 #[derive(Resource)]
@@ -32,16 +45,27 @@ pub struct Bullet {
     live_time: Timer,
 }
 
+// 投掷速度
+const THROW_SPEED: f32 = 23.0;
+// 投掷物命中的伤害
+const THROW_DAMAGE: f32 = 10.0;
+
+const FOX_PATH: &str = "models/Fox.glb";
+// 狐狸模型大约 80 高, 缩放到和碰撞球差不多大
+const FOX_SCALE: f32 = 0.012;
+
 const collider_player: Group = Group::GROUP_1;
 const collider_ground: Group = Group::GROUP_2;
 const collider_ball: Group = Group::GROUP_3;
 
 pub fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut graphs: ResMut<Assets<AnimationGraph>>,
     asset_server: Res<AssetServer>,
 ) {
+    let spawn_point = SpawnPoint::default();
+    let config = MovementConfig::default();
+
     // 角色
     commands.spawn((
         KinematicCharacterController {
@@ -60,26 +84,40 @@ pub fn setup(
         RigidBody::Dynamic,
         Collider::ball(0.5),
         GravityScale(4.0),
-        Mesh3d(meshes.add(Cuboid::new(1.0, 1.0, 1.0))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        // SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/Fox.glb"))),
-        Transform::from_xyz(0.0, 10.0, 0.0),
+        Transform::from_translation(spawn_point.0),
+        Visibility::default(),
         Player,
+        Health::default(),
+        StreamingAnchor,
+        // 跳跃和移动状态
+        (
+            Grounded::default(),
+            JumpState::default(),
+            GravityProfile::default(),
+            Movement::default(),
+            Stamina::default(),
+            fox_animation(&asset_server, &mut graphs, &config),
+            config,
+        ),
+        // 游泳和憋气
+        (
+            Submersion::default(),
+            SwimConfig::default(),
+            Breath::default(),
+        ),
         Velocity::zero(),
         Ccd::enabled(),
         // CollisionGroups::new(collider_player, collider_ground),
+        // 模型的脚放在碰撞球底部, 头朝角色的前方 (-x)
+        children![(
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(FOX_PATH))),
+            Transform::from_xyz(0.0, -0.5, 0.0)
+                .with_rotation(Quat::from_rotation_y(-PI / 2.0))
+                .with_scale(Vec3::splat(FOX_SCALE)),
+        )],
     ));
 
-    // 平滑摄像机
-    let camera_at = CameraLookAt {
-        look_at: Vec3::new(0.0, 0.0, 0.0) - Vec3::new(20.0, 12.0, 0.0),
-    };
-    commands.insert_resource(camera_at);
-    commands.spawn((
-        LookTransform::new(Vec3::ZERO, Vec3::ZERO, Vec3::Y),
-        Smoother::new(0.9),
-        Camera3d::default(),
-    ));
+    commands.insert_resource(spawn_point);
 
     // todo 投掷物品
     let pokeball_handle = asset_server.load("models/pokeball.glb");
@@ -92,41 +130,28 @@ pub fn setup(
 
 pub fn handle_mouse_motion(
     mut commands: Commands,
-    mut mouse_motion_events: EventReader<MouseMotion>,
-    mut camera_look_at: ResMut<CameraLookAt>,
+    camera_look_at: Res<CameraLookAt>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mouse: Res<ButtonInput<MouseButton>>,
+    actions: Res<ActionState>,
     my_asset_packet: Res<MyAssetPacket>,
     gltf_asset: Res<Assets<Gltf>>,
     gltf_node_asset: Res<Assets<GltfNode>>,
     gltf_mesh_asset: Res<Assets<GltfMesh>>,
-    mut player_position_query: Query<&mut Transform, With<Player>>,
+    mut player_position_query: Query<(Entity, &mut Transform), With<Player>>,
     // bullet_query: Query<&Transform, With<Bullet>>,
     mut key_cool_timer_query: Query<&mut KeyCooldownTimer>,
     mut print_timer_query: Query<&mut PrintTimer>,
     time: Res<Time>,
 ) {
-    let displacement = mouse_motion_events
-        .read()
-        .fold(0., |acc, mouse_motion| acc + mouse_motion.delta.x);
-
-    // 旋转
-    let mut camera_transform = Transform::from_translation(camera_look_at.look_at);
-    camera_transform.rotate_around(Vec3::ZERO, Quat::from_rotation_y(-displacement / 500.));
-    camera_look_at.look_at = Vec3::new(
-        camera_transform.translation.x,
-        camera_transform.translation.y,
-        camera_transform.translation.z,
-    );
-
-    if let Ok(mut player_position) = player_position_query.single_mut() {
-        player_position.rotate_local_y(-displacement / 500.);
+    if let Ok((player, mut player_position)) = player_position_query.single_mut() {
+        // 角色朝向跟随摄像机
+        player_position.rotation = Quat::from_rotation_y(camera_look_at.yaw);
         if let Ok(mut key_cool_timer) = key_cool_timer_query.single_mut() {
             // 鼠标
             let key_cool_timer = &mut key_cool_timer.0;
             key_cool_timer.tick(time.delta());
-            if mouse.pressed(MouseButton::Left) && key_cool_timer.finished() {
+            if actions.pressed(Action::Throw) && key_cool_timer.finished() {
                 if let Some(obj_mesh) = gltf_asset
                     .get(&my_asset_packet.0)
                     .and_then(|gltf| {
@@ -150,15 +175,26 @@ pub fn handle_mouse_motion(
                         Collider::ball(1.0),
                         // ColliderConstructor::ConvexHullFromMesh,
                         GravityScale(1.0),
+                        // 掉进水里会浮起来
+                        (
+                            Submersion::default(),
+                            Buoyancy::default(),
+                            Damping::default(),
+                        ),
                         Transform::from_translation(
                             player_position.translation.clone() + Vec3::new(0.0, 2.0, 0.0),
                         )
                         .with_scale(Vec3::new(0.2, 0.2, 0.2)),
                         Velocity {
-                            linvel: camera_transform.translation,
+                            linvel: camera_look_at.forward() * THROW_SPEED,
                             angvel: Vec3::ZERO,
                         },
                         Ccd::enabled(),
+                        Projectile {
+                            damage: THROW_DAMAGE,
+                            owner: player,
+                        },
+                        ActiveEvents::COLLISION_EVENTS,
                         // CollisionGroups::new(collider_ball, collider_ground),
                     ));
                 }
@@ -176,62 +212,26 @@ pub fn handle_mouse_motion(
 }
 
 pub fn handle_keyboard_controls(
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
     camera_look_at: Res<CameraLookAt>,
-    time: Res<Time>,
-    mut controller_query: Query<&mut KinematicCharacterController, With<Player>>,
-    mut player_velocity_query: Query<&mut Velocity, With<Player>>,
+    mut movement_query: Query<&mut Movement, With<Player>>,
 ) {
-    let mut look_direction = camera_look_at.look_at.normalize();
+    let mut look_direction = camera_look_at.look_at;
     look_direction.y = 0.0;
+    let look_direction = look_direction.normalize_or_zero();
     let rotation_quaternion = Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2);
     let look_direction_rotation = rotation_quaternion.mul_vec3(look_direction);
 
-    let mut direction = Vec3::ZERO;
-
-    if keyboard.pressed(KeyCode::KeyW) {
-        direction += look_direction;
-    }
-    if keyboard.pressed(KeyCode::KeyS) {
-        direction -= look_direction;
-    }
-    if keyboard.pressed(KeyCode::KeyA) {
-        direction -= look_direction_rotation;
-    }
-    if keyboard.pressed(KeyCode::KeyD) {
-        direction += look_direction_rotation;
-    }
-
-    // 角色位移
-    let mut direc = direction.normalize_or_zero() * 20.0 * time.delta_secs();
-
-    // 跳跃
-    if keyboard.pressed(KeyCode::Space) {
-        if let Ok(mut player_velocity) = player_velocity_query.single_mut() {
-            player_velocity.linvel.y = 15.0;
-        }
-    }
+    let direction =
+        look_direction * actions.movement.y + look_direction_rotation * actions.movement.x;
 
-    if let Ok(mut controller) = controller_query.single_mut() {
-        controller.translation = Some(direc);
+    // 角色位移由 locomotion::update_movement 按移动状态计算
+    // 手柄推杆幅度决定速度
+    if let Ok(mut movement) = movement_query.single_mut() {
+        movement.input = direction.clamp_length_max(1.0);
     };
 }
 
-pub fn handle_camera(
-    camera_look_at: Res<CameraLookAt>,
-    player_position_query: Query<&Transform, With<Player>>,
-    mut look_transform_query: Query<&mut LookTransform>,
-) {
-    // 更新摄像机位置
-    let Ok(mut lt) = look_transform_query.single_mut() else {
-        return;
-    };
-    if let Ok(player_position) = player_position_query.single() {
-        lt.eye = player_position.translation - camera_look_at.look_at;
-        lt.target = player_position.translation;
-    }
-}
-
 pub fn del_bullet(
     time: Res<Time>,
     mut commands: Commands,
@@ -244,3 +244,31 @@ pub fn del_bullet(
         }
     }
 }
+
+// 狐狸只有待机, 走和跑三个动画, 其他状态借用它们
+fn fox_animation(
+    asset_server: &AssetServer,
+    graphs: &mut Assets<AnimationGraph>,
+    config: &MovementConfig,
+) -> AnimationDriver {
+    let clip =
+        |index: usize| asset_server.load(GltfAssetLabel::Animation(index).from_asset(FOX_PATH));
+    let (survey, walk, run) = (clip(0), clip(1), clip(2));
+    AnimationDriver::new(
+        graphs,
+        vec![
+            (MovementState::Idle, survey, 0.0),
+            (MovementState::Walk, walk.clone(), config.walk_speed),
+            (
+                MovementState::SlowWalk,
+                walk.clone(),
+                config.slow_walk_speed,
+            ),
+            (MovementState::Crouch, walk.clone(), config.crouch_speed),
+            (MovementState::Swim, walk, config.swim_speed),
+            (MovementState::Sprint, run.clone(), config.sprint_speed),
+            (MovementState::Airborne, run.clone(), 0.0),
+            (MovementState::Fly, run, config.fly_speed),
+        ],
+    )
+}
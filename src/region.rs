@@ -1,17 +1,17 @@
+use std::collections::HashMap;
 use std::ops::Add;
 use std::time::Instant;
 
+use crate::block_provider::model::{self, BlockModels, BlockRenderMode};
 use crate::block_provider::MapGeneratorInfo;
-use crate::player::Player;
+use crate::util::export::{export_glb, export_obj, ExportMaterial, ExportMesh};
 use crate::util::Triangle;
-use bevy::gltf::{Gltf, GltfMesh, GltfNode};
+use bevy::gltf::{Gltf, GltfMesh};
 use bevy::math::VectorSpace;
 use bevy::pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder};
 use bevy::prelude::*;
 use bevy::render::render_resource::{Extent3d, Texture, TextureDimension, TextureFormat};
-use bevy::render::{
-    mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology,
-};
+use bevy::render::render_asset::RenderAssetUsages;
 use bevy::scene::ron::de;
 use bevy_rapier3d::prelude::*;
 use rand::Rng;
@@ -19,29 +19,38 @@ use simdnoise::*;
 
 #[derive(Component, Debug)]
 pub struct ViewRegion {
-    block_x: i32,
-    block_y: i32,
-    block_z: i32,
+    pub block_x: i32,
+    pub block_y: i32,
+    pub block_z: i32,
 }
 
 #[derive(Component, Debug)]
 pub struct RigidRegion {
-    block_x: i32,
-    block_y: i32,
-    block_z: i32,
+    pub block_x: i32,
+    pub block_y: i32,
+    pub block_z: i32,
 }
 
-#[derive(Resource)]
-pub struct LowPolySanBlockAsset(Handle<Gltf>);
+// 区块围绕这个实体加载, 平时是角色, 观察者模式下是自由摄像机
+#[derive(Component, Debug)]
+pub struct StreamingAnchor;
+
+// 区块的显示方式: 方块网格或 glTF 模型;
+// 在 main 里把它换成 RegionRenderMode::Model(..) 使用方块模型
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RegionRenderMode {
+    #[default]
+    Cube,
+    Model(BlockRenderMode),
+}
 
 const collider_player: Group = Group::GROUP_1;
 const collider_ground: Group = Group::GROUP_2;
 const collider_ball: Group = Group::GROUP_3;
 
 pub fn startup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // 加载 .glb 文件
-    let low_poly_sand_block_handle = asset_server.load("models/stylized_low-poly_sand_block.glb");
-    commands.insert_resource(LowPolySanBlockAsset(low_poly_sand_block_handle));
+    // 加载方块模型 .glb 文件
+    commands.insert_resource(BlockModels::load(&asset_server));
 
     // 环境光
     commands.insert_resource(AmbientLight {
@@ -73,12 +82,12 @@ pub fn region_update(
     mut color_textures: ResMut<Assets<Image>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    player_position_query: Query<&Transform, With<Player>>,
+    player_position_query: Query<&Transform, With<StreamingAnchor>>,
     view_region_entity: Query<(Entity, &ViewRegion), With<ViewRegion>>,
     rigid_region_entity: Query<(Entity, &RigidRegion), With<RigidRegion>>,
-    sand_block_query: Res<LowPolySanBlockAsset>,
+    mut block_models: ResMut<BlockModels>,
+    region_render_mode: Res<RegionRenderMode>,
     gltf_asset: Res<Assets<Gltf>>,
-    gltf_node_asset: Res<Assets<GltfNode>>,
     gltf_mesh_asset: Res<Assets<GltfMesh>>,
     asset_server: Res<AssetServer>,
     map_generator_info_query: Query<&MapGeneratorInfo>,
@@ -128,13 +137,21 @@ pub fn region_update(
         }
     }
 
+    // 模型方块需要等模型加载完成
+    block_models.update(
+        &asset_server,
+        &gltf_asset,
+        &gltf_mesh_asset,
+        &mut meshes,
+        &mut materials,
+    );
+    let models_ready = match *region_render_mode {
+        RegionRenderMode::Cube => true,
+        RegionRenderMode::Model(_) => block_models.ready(),
+    };
+
     // view地形 默认加载周围(view_circle * view_circle)的区块
-    if let Some(obj_mesh) = gltf_asset
-        .get(&sand_block_query.0)
-        .and_then(|gltf| gltf_node_asset.get(&gltf.named_nodes["block"]))
-        .and_then(|floor_dirt| floor_dirt.mesh.as_ref())
-        .and_then(|floor_mesh_handle| gltf_mesh_asset.get(floor_mesh_handle))
-    {
+    if models_ready {
         // 加载 PBR 贴图
         let base_color_texture: Handle<Image> = asset_server.load("textures/old.png");
         let normal_texture: Handle<Image> = asset_server.load("textures/grass_block_top_n.png");
@@ -166,8 +183,6 @@ pub fn region_update(
                         Ok(v) => v,
                         Err(e) => {return;}
                     };
-                    let region_mesh = map_generator_info.region_generate(region_x, 0, region_z);
-
                     // 区块偏移
                     let plain_size = 16i32;
                     let region_transform = Transform::from_xyz(
@@ -175,22 +190,48 @@ pub fn region_update(
                         0f32,
                         region_z as f32 * plain_size as f32,
                     );
+                    let view_region = ViewRegion {
+                        block_x: region_x,
+                        block_y: 0,
+                        block_z: region_z,
+                    };
 
-                    // let block_mesh: &Mesh = meshes.get(&obj_mesh.primitives[0].mesh).unwrap();
-                    // let region_mesh: Mesh = region_by_mesh(region_x, region_z, block_mesh);
-                    commands.spawn((
-                        ViewRegion {
-                            block_x: region_x,
-                            block_y: 0,
-                            block_z: region_z,
-                        },
-                        // Mesh3d(meshes.add(region_mesh)),
-                        // MeshMaterial3d(obj_mesh.primitives[0].material.clone().unwrap()),
-                        Mesh3d(meshes.add(region_mesh)),
-                        // MeshMaterial3d(cube_material.clone()),
-                        MeshMaterial3d(material.clone()),
-                        region_transform,
-                    ));
+                    match *region_render_mode {
+                        RegionRenderMode::Cube => {
+                            let region_mesh =
+                                map_generator_info.region_generate(region_x, 0, region_z);
+                            commands.spawn((
+                                view_region,
+                                Mesh3d(meshes.add(region_mesh)),
+                                // MeshMaterial3d(cube_material.clone()),
+                                MeshMaterial3d(material.clone()),
+                                region_transform,
+                            ));
+                        }
+                        RegionRenderMode::Model(BlockRenderMode::Merged) => {
+                            let height_map = map_generator_info.height_map(region_x, 0, region_z);
+                            let parts = model::region_by_models(&height_map, &block_models, &meshes);
+                            commands
+                                .spawn((view_region, region_transform, Visibility::default()))
+                                .with_children(|parent| {
+                                    for (mesh, material) in parts {
+                                        parent.spawn((
+                                            Mesh3d(meshes.add(mesh)),
+                                            MeshMaterial3d(material),
+                                            Transform::default(),
+                                        ));
+                                    }
+                                });
+                        }
+                        RegionRenderMode::Model(BlockRenderMode::Instanced) => {
+                            let height_map = map_generator_info.height_map(region_x, 0, region_z);
+                            commands
+                                .spawn((view_region, region_transform, Visibility::default()))
+                                .with_children(|parent| {
+                                    model::spawn_region_instances(parent, &height_map, &block_models);
+                                });
+                        }
+                    }
                 }
             }
         }
@@ -230,43 +271,110 @@ pub fn region_update(
                     Ok(v) => v,
                     Err(e) => {return;}
                 };
-                let height_map = map_generator_info.height_map(region_x, 0, region_z);
-                let heights = height_map.into_iter().flatten().collect::<Vec<f32>>();
-                let plain_size = 16;
-                let height_map_collider = Collider::heightfield(
-                    heights,
-                    plain_size as usize,
-                    plain_size as usize,
-                    Vec3::new(plain_size as f32, 1.0, plain_size as f32),
-                );
-
-                // 区块偏移
-                let plain_size = 16i32;
-                let region_transform = Transform::from_xyz(
-                    region_x as f32 * plain_size as f32 + (plain_size as f32 / 2.0) - 0.5f32,
-                    0.5f32,
-                    region_z as f32 * plain_size as f32 + (plain_size as f32 / 2.0) - 0.5f32,
-                );
+                // 地图生成器决定碰撞形状: 高度场或三角网格
+                let region_collider = map_generator_info.collider(region_x, 0, region_z);
 
                 let start = Instant::now();
-                commands.spawn((
-                    RigidRegion {
-                        block_x: region_x,
-                        block_y: 0,
-                        block_z: region_z,
-                    },
-                    RigidBody::Fixed,
-                    // trimesh,
-                    height_map_collider,
-                    // CollisionGroups::new(collider_ground, collider_player | collider_ball ),
-                    region_transform,
-                ));
+                let mut rigid_region = commands.spawn(RigidRegion {
+                    block_x: region_x,
+                    block_y: 0,
+                    block_z: region_z,
+                });
+                // 空区块只留下标记, 免得每帧重新生成
+                if let Some((region_collider, region_transform)) = region_collider {
+                    rigid_region.insert((
+                        RigidBody::Fixed,
+                        // trimesh,
+                        region_collider,
+                        // CollisionGroups::new(collider_ground, collider_player | collider_ball ),
+                        region_transform,
+                    ));
+                }
                 println!("spawn time: {}", (Instant::now() - start).as_secs_f32());
             }
         }
     }
 }
 
+// 按 F9 把已加载的区块导出到 export/regions.obj 和 export/regions.glb
+pub fn region_export(
+    key: Res<ButtonInput<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    view_region_entity: Query<(Entity, &ViewRegion)>,
+    children_query: Query<&Children>,
+    mesh_query: Query<(
+        &Mesh3d,
+        Option<&MeshMaterial3d<StandardMaterial>>,
+        &GlobalTransform,
+    )>,
+) {
+    if !key.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    let start = Instant::now();
+    let mut export_materials: HashMap<AssetId<StandardMaterial>, ExportMaterial> = HashMap::new();
+    let mut export_meshes: Vec<ExportMesh> = Vec::new();
+    for (entity, view_region) in view_region_entity.iter() {
+        // 方块模式网格在区块实体上, 模型模式在子实体上
+        let entities = std::iter::once(entity).chain(children_query.iter_descendants(entity));
+        for (index, (mesh3d, material3d, transform)) in entities
+            .filter_map(|e| mesh_query.get(e).ok())
+            .enumerate()
+        {
+            let Some(mesh) = meshes.get(&mesh3d.0) else {
+                continue;
+            };
+            let material = material3d
+                .map(|m| {
+                    let count = export_materials.len();
+                    export_materials
+                        .entry(m.0.id())
+                        .or_insert_with(|| {
+                            let standard = materials.get(&m.0);
+                            ExportMaterial {
+                                name: format!("material_{}", count),
+                                base_color: standard
+                                    .map_or([1.0; 4], |s| s.base_color.to_srgba().to_f32_array()),
+                                texture: standard
+                                    .and_then(|s| s.base_color_texture.as_ref())
+                                    .and_then(|t| asset_server.get_path(t))
+                                    // mtl 在 export/ 下, 贴图路径相对于它
+                                    .map(|path| format!("../assets/{}", path.path().display())),
+                            }
+                        })
+                        .clone()
+                })
+                .unwrap_or_default();
+            export_meshes.push(
+                ExportMesh::new(
+                    format!("region_{}_{}_{}", view_region.block_x, view_region.block_z, index),
+                    Triangle::from_mesh(mesh),
+                )
+                .with_transform(transform.compute_transform())
+                .with_material(material),
+            );
+        }
+    }
+
+    for result in [
+        export_obj("export/regions.obj", &export_meshes),
+        export_glb("export/regions.glb", &export_meshes),
+    ] {
+        if let Err(e) = result {
+            println!("region export fail: {}", e);
+            return;
+        }
+    }
+    println!(
+        "region export {} meshes time: {}",
+        export_meshes.len(),
+        (Instant::now() - start).as_secs_f32()
+    );
+}
+
 fn in_region(bx: i32, by: i32, bz: i32, px: i32, py: i32, pz: i32, region: i32) -> bool {
     if (bx - px).abs() <= region && (bz - pz).abs() <= region {
         return true;
@@ -279,7 +387,6 @@ fn create_plain_mesh(height_mesh: &Vec<Vec<f32>>, transform: Transform) -> Mesh
     let plain_size = 16i32;
     let mut attribute_position: Vec<[f32; 3]> = Vec::new();
     let mut attribute_uv_0: Vec<[f32; 2]> = Vec::new();
-    let mut attribute_normal: Vec<[f32; 3]> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
 
     for (x_index, z_list) in height_mesh.iter().enumerate() {
@@ -294,9 +401,6 @@ fn create_plain_mesh(height_mesh: &Vec<Vec<f32>>, transform: Transform) -> Mesh
             // uv
             let uv_size = 1f32 / plain_size as f32;
             attribute_uv_0.push([uv_size * x_index as f32, uv_size * z_index as f32]);
-
-            // 法线
-            attribute_normal.push([0.0, 1.0, 0.0]);
         }
     }
 
@@ -312,14 +416,15 @@ fn create_plain_mesh(height_mesh: &Vec<Vec<f32>>, transform: Transform) -> Mesh
         }
     }
 
-    Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    // 法线按起伏平滑计算
+    Triangle::new_indexed(
+        attribute_position.into_iter().map(Vec3::from_array).collect(),
+        vec![],
+        attribute_uv_0.into_iter().map(|uv| Vec3::new(uv[0], uv[1], 0.0)).collect(),
+        indices,
     )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, attribute_position)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, attribute_normal)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, attribute_uv_0)
-    .with_inserted_indices(Indices::U32(indices))
+    .smooth_normals(std::f32::consts::PI)
+    .build()
 }
 
 fn create_texture(region_x: i32, region_z: i32) -> Image {
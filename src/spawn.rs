@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::block_provider::MapGeneratorInfo;
+use crate::npc::Npc;
+use crate::player::{Player, SpawnPoint};
+use crate::structure;
+use crate::water::Water;
+
+// 出生时身体中心在方块顶面以上的高度
+const SPAWN_CLEARANCE: f32 = 1.0;
+// 往下找地面的最远距离
+const GROUND_PROBE: f32 = 1000.0;
+
+// 出生点搜索参数, 在 main 里替换这个资源可以换一个搜索中心
+#[derive(Resource, Clone, Debug)]
+pub struct SpawnConfig {
+    // 从这里开始往外找, 世界方块坐标 (x, z)
+    pub origin: Vec2,
+    pub search_radius: i32,
+    // 周围 flat_radius 格内的高差不超过 max_step
+    pub flat_radius: i32,
+    pub max_step: f32,
+    // 周围 open_radius 格内没有比脚下高出 max_overhead 的地形, 不会出生在坑里或崖壁下
+    pub open_radius: i32,
+    pub max_overhead: f32,
+    // 地面至少比水面高这么多
+    pub min_above_water: f32,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        SpawnConfig {
+            origin: Vec2::ZERO,
+            search_radius: 64,
+            flat_radius: 2,
+            max_step: 1.0,
+            open_radius: 4,
+            max_overhead: 3.0,
+            min_above_water: 1.0,
+        }
+    }
+}
+
+// 脚下的刚体区块加载之前冻结在空中
+#[derive(Component, Debug)]
+pub struct AwaitingGround;
+
+// heights 为 [x][z], 返回离 center 最近的合格格子
+fn find_column(
+    heights: &[Vec<f32>],
+    center: (usize, usize),
+    config: &SpawnConfig,
+    water_level: f32,
+    blocked: impl Fn(usize, usize) -> bool,
+) -> Option<(usize, usize)> {
+    let size_x = heights.len() as i32;
+    let size_z = heights.first().map_or(0, |z_list| z_list.len()) as i32;
+    let border = config.flat_radius.max(config.open_radius);
+    let around = |x: i32, z: i32, radius: i32| {
+        (x - radius..=x + radius)
+            .flat_map(move |nx| (z - radius..=z + radius).map(move |nz| (nx, nz)))
+            .map(|(nx, nz)| heights[nx as usize][nz as usize])
+    };
+
+    let mut candidates: Vec<(i32, i32)> = (border..size_x - border)
+        .flat_map(|x| (border..size_z - border).map(move |z| (x, z)))
+        .collect();
+    let (cx, cz) = (center.0 as i32, center.1 as i32);
+    candidates.sort_by_key(|(x, z)| (x - cx).pow(2) + (z - cz).pow(2));
+
+    candidates
+        .into_iter()
+        .find(|&(x, z)| {
+            let h = heights[x as usize][z as usize];
+            // 方块顶面在 h + 0.5
+            h + 0.5 >= water_level + config.min_above_water
+                && around(x, z, config.flat_radius).all(|n| (n - h).abs() <= config.max_step)
+                && around(x, z, config.open_radius).all(|n| n - h <= config.max_overhead)
+                && !blocked(x as usize, z as usize)
+        })
+        .map(|(x, z)| (x as usize, z as usize))
+}
+
+// 在 config.origin 附近找一个干燥, 平坦, 开阔且没有建筑的位置
+pub fn find_spawn_point(
+    map_generator_info: &MapGeneratorInfo,
+    config: &SpawnConfig,
+    water_level: f32,
+) -> Option<Vec3> {
+    let border = config.flat_radius.max(config.open_radius);
+    let radius = config.search_radius + border;
+    let min = config.origin.round().as_ivec2() - IVec2::splat(radius);
+    let size = (radius * 2 + 1) as usize;
+    let heights = map_generator_info.height_area(min.x, min.y, size, size);
+    let sites = structure::sites_in_area(min, min + IVec2::splat(radius * 2), map_generator_info);
+
+    let center = (radius as usize, radius as usize);
+    let (x, z) = find_column(&heights, center, config, water_level, |x, z| {
+        let (wx, wz) = (min.x + x as i32, min.y + z as i32);
+        sites.iter().any(|site| site.contains(wx, wz, 1))
+    })?;
+    Some(Vec3::new(
+        (min.x + x as i32) as f32,
+        heights[x][z] + 0.5 + SPAWN_CLEARANCE,
+        (min.y + z as i32) as f32,
+    ))
+}
+
+// 启动时替换写死的出生点, 角色和 NPC 冻结到地面加载
+pub fn setup(
+    mut commands: Commands,
+    config: Res<SpawnConfig>,
+    water: Res<Water>,
+    map_generator_info_query: Query<&MapGeneratorInfo>,
+    mut spawn_point: ResMut<SpawnPoint>,
+    mut player_query: Query<(Entity, &mut Transform), With<Player>>,
+    mut npc_query: Query<(Entity, &mut Transform), (With<Npc>, Without<Player>)>,
+) {
+    let Ok(map_generator_info) = map_generator_info_query.single() else {
+        return;
+    };
+    let point = match find_spawn_point(map_generator_info, &config, water.level) {
+        Some(point) => point,
+        None => {
+            // 找不到时至少不要出生在地形里
+            let origin = config.origin.round().as_ivec2();
+            let height = map_generator_info.height_area(origin.x, origin.y, 1, 1)[0][0];
+            println!("no safe spawn point near {}, use origin", config.origin);
+            Vec3::new(
+                origin.x as f32,
+                height.max(water.level) + 0.5 + SPAWN_CLEARANCE,
+                origin.y as f32,
+            )
+        }
+    };
+    println!("spawn point: {}", point);
+    spawn_point.0 = point;
+
+    for (entity, mut transform) in player_query.iter_mut() {
+        transform.translation = point;
+        commands
+            .entity(entity)
+            .insert((AwaitingGround, RigidBodyDisabled));
+    }
+    // 平坦范围内两格外的高差不超过 max_step, 抬高一点放下去
+    for (entity, mut transform) in npc_query.iter_mut() {
+        transform.translation = point + Vec3::new(2.0, config.max_step, 0.0);
+        commands
+            .entity(entity)
+            .insert((AwaitingGround, RigidBodyDisabled));
+    }
+}
+
+// 脚下的地形碰撞体加载后解冻, 不管是高度场还是三角网格
+pub fn release_on_ground(
+    mut commands: Commands,
+    rapier_context: ReadRapierContext,
+    awaiting_query: Query<(Entity, &Transform), With<AwaitingGround>>,
+) {
+    let Ok(context) = rapier_context.single() else {
+        return;
+    };
+    // 地形是固定刚体, 冻结中的角色和 NPC 不算
+    let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+    for (entity, transform) in awaiting_query.iter() {
+        let ground = context.cast_ray(
+            transform.translation,
+            Vec3::NEG_Y,
+            GROUND_PROBE,
+            true,
+            filter,
+        );
+        if ground.is_some() {
+            commands
+                .entity(entity)
+                .remove::<(AwaitingGround, RigidBodyDisabled)>();
+        }
+    }
+}
+
+// 给角色操作的系统用的运行条件
+pub fn player_ready(player_query: Query<(), (With<Player>, With<AwaitingGround>)>) -> bool {
+    player_query.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_column() {
+        let config = SpawnConfig {
+            flat_radius: 1,
+            open_radius: 2,
+            ..default()
+        };
+        let mut heights = vec![vec![0.0; 15]; 15];
+        let found = find_column(&heights, (7, 7), &config, -10.0, |_, _| false);
+        assert_eq!(found, Some((7, 7)));
+
+        // 中心有一根柱子, 三格以内都不合格
+        heights[7][7] = 10.0;
+        let found = find_column(&heights, (7, 7), &config, -10.0, |_, _| false);
+        assert_eq!(found, Some((4, 7)));
+        let found = find_column(&heights, (7, 7), &config, -10.0, |x, _| x < 7);
+        assert_eq!(found, Some((7, 4)));
+
+        // 全在水下
+        assert!(find_column(&heights, (7, 7), &config, 5.0, |_, _| false).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use smooth_bevy_cameras::{LookTransform, Smoother};
+
+use crate::camera::CameraLookAt;
+use crate::health::Dead;
+use crate::input::{Action, ActionState};
+use crate::locomotion::Flying;
+use crate::player::Player;
+use crate::region::StreamingAnchor;
+use crate::spawn::AwaitingGround;
+
+// 穿墙的自由摄像机, 存在时角色冻结在原地
+#[derive(Component, Debug)]
+pub struct Spectator {
+    pub speed: f32,
+    pub sprint_speed: f32,
+}
+
+impl Default for Spectator {
+    fn default() -> Self {
+        Spectator {
+            speed: 30.0,
+            sprint_speed: 120.0,
+        }
+    }
+}
+
+// 切换角色的飞行模式
+pub fn toggle_fly(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    mut player_query: Query<(Entity, Has<Flying>, &mut GravityScale, &mut Velocity), With<Player>>,
+) {
+    if !actions.just_pressed(Action::ToggleFly) {
+        return;
+    }
+    let Ok((entity, flying, mut gravity_scale, mut velocity)) = player_query.single_mut() else {
+        return;
+    };
+    if flying {
+        // 重力由 locomotion::handle_jump 恢复
+        commands.entity(entity).remove::<Flying>();
+    } else {
+        commands.entity(entity).insert(Flying);
+        gravity_scale.0 = 0.0;
+        velocity.linvel = Vec3::ZERO;
+    }
+}
+
+// 进入观察者模式时从摄像机位置放出自由摄像机, 区块跟着它加载
+pub fn toggle_noclip(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    look_transform_query: Query<&LookTransform>,
+    player_query: Query<(Entity, Has<Dead>), With<Player>>,
+    spectator_query: Query<Entity, With<Spectator>>,
+) {
+    if !actions.just_pressed(Action::ToggleNoclip) {
+        return;
+    }
+    let Ok((player, dead)) = player_query.single() else {
+        return;
+    };
+    if let Ok(spectator) = spectator_query.single() {
+        commands.entity(spectator).despawn();
+        commands.entity(player).insert(StreamingAnchor);
+        // 角色附近的区块要重新加载, 等脚下有地面再由 spawn::release_on_ground 解冻;
+        // 死亡的角色由重生流程处理
+        if !dead {
+            commands.entity(player).insert(AwaitingGround);
+        }
+    } else {
+        let eye = look_transform_query
+            .single()
+            .map_or(Vec3::ZERO, |lt| lt.eye);
+        commands.spawn((
+            Spectator::default(),
+            Transform::from_translation(eye),
+            StreamingAnchor,
+        ));
+        // 角色附近的区块可能被卸载, 冻结角色免得掉下去
+        commands
+            .entity(player)
+            .remove::<StreamingAnchor>()
+            .insert(RigidBodyDisabled);
+    }
+}
+
+// 沿视线方向飞, 不做碰撞检测
+pub fn move_spectator(
+    actions: Res<ActionState>,
+    camera_look_at: Res<CameraLookAt>,
+    time: Res<Time>,
+    mut spectator_query: Query<(&mut Transform, &Spectator)>,
+) {
+    let forward = camera_look_at.forward();
+    let right = forward.cross(Vec3::Y).normalize_or_zero();
+    let vertical = actions.pressed(Action::Jump) as i32 - actions.pressed(Action::Crouch) as i32;
+    let direction = forward * actions.movement.y + right * actions.movement.x;
+    let direction = (direction + Vec3::Y * vertical as f32).clamp_length_max(1.0);
+    for (mut transform, spectator) in spectator_query.iter_mut() {
+        let speed = if actions.pressed(Action::Sprint) {
+            spectator.sprint_speed
+        } else {
+            spectator.speed
+        };
+        transform.translation += direction * speed * time.delta_secs();
+    }
+}
+
+// 观察者模式下代替 camera::handle_camera
+pub fn spectator_camera(
+    camera_look_at: Res<CameraLookAt>,
+    spectator_query: Query<&Transform, With<Spectator>>,
+    mut player_query: Query<&mut Visibility, With<Player>>,
+    mut look_transform_query: Query<(&mut LookTransform, &mut Smoother)>,
+) {
+    let Ok(spectator) = spectator_query.single() else {
+        return;
+    };
+    let Ok((mut lt, mut smoother)) = look_transform_query.single_mut() else {
+        return;
+    };
+    lt.eye = spectator.translation;
+    lt.target = spectator.translation + camera_look_at.forward();
+    smoother.set_lag_weight(0.0);
+
+    // 第一人称时隐藏的角色要能看到
+    if let Ok(mut visibility) = player_query.single_mut() {
+        visibility.set_if_neq(Visibility::Inherited);
+    }
+}
+
+// 给角色和跟随摄像机的系统用的运行条件
+pub fn not_spectating(spectator_query: Query<(), With<Spectator>>) -> bool {
+    spectator_query.is_empty()
+}
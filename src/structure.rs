@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+
+use crate::block_provider::{MapGeneratorInfo, WORLD_SEED};
+use crate::region::ViewRegion;
+use crate::util::Triangle;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// 一个网格单元包含 4*4 个区块, 每个单元最多放置一个建筑
+const CELL_REGIONS: i32 = 4;
+const PLAIN_SIZE: i32 = 16;
+const CELL_SIZE: i32 = CELL_REGIONS * PLAIN_SIZE;
+// 每个单元尝试的候选位置数量
+const SITE_TRIES: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    House,
+    Ruin,
+    Dungeon,
+}
+
+impl StructureKind {
+    // 占地边长
+    fn size(&self) -> i32 {
+        match self {
+            StructureKind::House => 7,
+            StructureKind::Ruin => 9,
+            StructureKind::Dungeon => 9,
+        }
+    }
+
+    // 占地范围内允许的最大高差
+    fn max_slope(&self) -> f32 {
+        match self {
+            StructureKind::House => 3.0,
+            StructureKind::Ruin => 6.0,
+            StructureKind::Dungeon => 2.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StructureBlock {
+    Stone,
+    Plank,
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StructureProp {
+    Table,
+    Crate,
+    Chest,
+    Bucket,
+}
+
+impl StructureProp {
+    fn model(&self) -> &'static str {
+        match self {
+            StructureProp::Table => "models/stylized_low-poly_wood_table.glb",
+            StructureProp::Crate => "models/stylized_low-poly_wood_crate.glb",
+            StructureProp::Chest => "models/stylized_low-poly_wood_metal_chest.glb",
+            StructureProp::Bucket => "models/stylized_low-poly_wood_metal_bucket.glb",
+        }
+    }
+
+    // 模型原始尺寸差别很大, 统一缩放到一个方块左右
+    fn scale(&self) -> f32 {
+        match self {
+            StructureProp::Table => 0.006,
+            StructureProp::Crate => 0.025,
+            StructureProp::Chest => 0.01,
+            StructureProp::Bucket => 0.0025,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StructureTemplate {
+    pub kind: StructureKind,
+    pub size: i32,
+    // 相对地基的方块坐标, y=1 为地面上第一层
+    pub blocks: Vec<(IVec3, StructureBlock)>,
+    // 摆件 (相对坐标, 朝向)
+    pub props: Vec<(Vec3, f32, StructureProp)>,
+}
+
+impl StructureTemplate {
+    pub fn build(kind: StructureKind, rng: &mut StdRng) -> StructureTemplate {
+        let mut template = match kind {
+            StructureKind::House => house(),
+            StructureKind::Ruin => ruin(rng),
+            StructureKind::Dungeon => dungeon(),
+        };
+        // 随机朝向
+        let turns = rng.gen_range(0..4);
+        for _ in 0..turns {
+            template.rotate();
+        }
+        template
+    }
+
+    // 绕占地中心顺时针旋转90度
+    fn rotate(&mut self) {
+        let last = (self.size - 1) as f32;
+        for (pos, _) in self.blocks.iter_mut() {
+            *pos = IVec3::new(self.size - 1 - pos.z, pos.y, pos.x);
+        }
+        for (pos, yaw, _) in self.props.iter_mut() {
+            *pos = Vec3::new(last - pos.z, pos.y, pos.x);
+            *yaw -= std::f32::consts::FRAC_PI_2;
+        }
+    }
+}
+
+fn walls(
+    size: i32,
+    height: i32,
+    block: StructureBlock,
+    door: bool,
+) -> Vec<(IVec3, StructureBlock)> {
+    let mut blocks = Vec::new();
+    let door_x = size / 2;
+    for y in 1..=height {
+        for x in 0..size {
+            for z in 0..size {
+                let edge = x == 0 || z == 0 || x == size - 1 || z == size - 1;
+                if !edge {
+                    continue;
+                }
+                // 门洞开在 z=0 这面墙的中间
+                if door && z == 0 && x == door_x && y <= 2 {
+                    continue;
+                }
+                blocks.push((IVec3::new(x, y, z), block));
+            }
+        }
+    }
+    blocks
+}
+
+fn roof(size: i32, y: i32, block: StructureBlock) -> Vec<(IVec3, StructureBlock)> {
+    let mut blocks = Vec::new();
+    for x in 0..size {
+        for z in 0..size {
+            blocks.push((IVec3::new(x, y, z), block));
+        }
+    }
+    blocks
+}
+
+fn house() -> StructureTemplate {
+    let size = StructureKind::House.size();
+    let mut blocks = walls(size, 4, StructureBlock::Plank, true);
+    // 四角立柱
+    for (x, z) in [(0, 0), (0, size - 1), (size - 1, 0), (size - 1, size - 1)] {
+        for y in 1..=4 {
+            blocks.retain(|(pos, _)| *pos != IVec3::new(x, y, z));
+            blocks.push((IVec3::new(x, y, z), StructureBlock::Log));
+        }
+    }
+    // 窗户
+    blocks.retain(|(pos, _)| !(pos.y == 3 && (pos.x == 0 || pos.x == size - 1) && pos.z == size / 2));
+    blocks.extend(roof(size, 5, StructureBlock::Log));
+
+    let center = (size - 1) as f32 / 2.0;
+    StructureTemplate {
+        kind: StructureKind::House,
+        size,
+        blocks,
+        props: vec![
+            (Vec3::new(center, 0.0, center), 0.0, StructureProp::Table),
+            (Vec3::new(1.0, 0.0, (size - 2) as f32), 0.0, StructureProp::Chest),
+            (Vec3::new((size - 2) as f32, 0.0, 1.0), 0.0, StructureProp::Bucket),
+        ],
+    }
+}
+
+fn ruin(rng: &mut StdRng) -> StructureTemplate {
+    let size = StructureKind::Ruin.size();
+    let mut blocks = walls(size, 4, StructureBlock::Stone, true);
+    // 越高的墙越容易坍塌
+    blocks.retain(|(pos, _)| rng.gen::<f32>() > pos.y as f32 * 0.2);
+
+    let mut props = Vec::new();
+    for _ in 0..rng.gen_range(1..=3) {
+        let x = rng.gen_range(1..size - 1) as f32;
+        let z = rng.gen_range(1..size - 1) as f32;
+        let yaw = rng.gen::<f32>() * std::f32::consts::TAU;
+        props.push((Vec3::new(x, 0.0, z), yaw, StructureProp::Crate));
+    }
+    StructureTemplate {
+        kind: StructureKind::Ruin,
+        size,
+        blocks,
+        props,
+    }
+}
+
+fn dungeon() -> StructureTemplate {
+    let size = StructureKind::Dungeon.size();
+    // 地形是高度图, 无法向下挖, 地牢做成地面上的厚墙石室
+    let mut blocks = walls(size, 4, StructureBlock::Stone, true);
+    for (pos, _) in walls(size - 2, 4, StructureBlock::Stone, false) {
+        blocks.push((pos + IVec3::new(1, 0, 1), StructureBlock::Stone));
+    }
+    // 内墙的门洞
+    blocks.retain(|(pos, _)| !(pos.z == 1 && pos.x == size / 2 && pos.y <= 2));
+    blocks.extend(roof(size, 5, StructureBlock::Stone));
+
+    StructureTemplate {
+        kind: StructureKind::Dungeon,
+        size,
+        blocks,
+        props: vec![
+            (Vec3::new(2.0, 0.0, (size - 3) as f32), 0.0, StructureProp::Chest),
+            (
+                Vec3::new((size - 3) as f32, 0.0, (size - 3) as f32),
+                std::f32::consts::PI,
+                StructureProp::Chest,
+            ),
+            (Vec3::new((size - 3) as f32, 0.0, 2.0), 0.0, StructureProp::Bucket),
+        ],
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StructureSite {
+    // 占地最小角的世界方块坐标, y 为地基高度
+    pub origin: IVec3,
+    pub template: StructureTemplate,
+    // 占地范围内的地形高度 [x][z]
+    heights: Vec<Vec<f32>>,
+}
+
+impl StructureSite {
+    // 世界方块 (x, z) 是否在占地范围内, margin 为向外扩的格数
+    pub fn contains(&self, x: i32, z: i32, margin: i32) -> bool {
+        let size = self.template.size;
+        x >= self.origin.x - margin
+            && x < self.origin.x + size + margin
+            && z >= self.origin.z - margin
+            && z < self.origin.z + size + margin
+    }
+
+    // 世界方块坐标下的所有方块, 包括填到地面的地基
+    fn world_blocks(&self) -> Vec<(IVec3, StructureBlock)> {
+        let mut blocks = Vec::new();
+        for (x, z_list) in self.heights.iter().enumerate() {
+            for (z, height) in z_list.iter().enumerate() {
+                for y in (*height as i32 + 1)..=self.origin.y {
+                    let pos = self.origin + IVec3::new(x as i32, 0, z as i32);
+                    blocks.push((IVec3::new(pos.x, y, pos.z), StructureBlock::Stone));
+                }
+            }
+        }
+        blocks.extend(
+            self.template
+                .blocks
+                .iter()
+                .map(|(pos, block)| (self.origin + *pos, *block)),
+        );
+        blocks
+    }
+}
+
+// 按种子和单元坐标确定的随机数
+fn cell_rng(cell_x: i32, cell_z: i32) -> StdRng {
+    let mut hash = (WORLD_SEED as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    hash ^= (cell_x as u32 as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash ^= (cell_z as u32 as u64).wrapping_mul(0x94D0_49BB_1331_11EB).rotate_left(32);
+    StdRng::seed_from_u64(hash)
+}
+
+// 选出一个单元内的建筑位置, 没有合适地形时返回None
+pub fn site_in_cell(
+    cell_x: i32,
+    cell_z: i32,
+    map_generator_info: &MapGeneratorInfo,
+) -> Option<StructureSite> {
+    let mut rng = cell_rng(cell_x, cell_z);
+    if rng.gen::<f32>() > 0.6 {
+        return None;
+    }
+    let kind = match rng.gen_range(0..10) {
+        0..=4 => StructureKind::House,
+        5..=7 => StructureKind::Ruin,
+        _ => StructureKind::Dungeon,
+    };
+    let template = StructureTemplate::build(kind, &mut rng);
+
+    for _ in 0..SITE_TRIES {
+        // 保证占地不超出单元, 相邻单元的建筑不会重叠
+        let x = cell_x * CELL_SIZE + rng.gen_range(1..CELL_SIZE - template.size - 1);
+        let z = cell_z * CELL_SIZE + rng.gen_range(1..CELL_SIZE - template.size - 1);
+        let heights = map_generator_info.height_area(
+            x,
+            z,
+            template.size as usize,
+            template.size as usize,
+        );
+        let (min, max) = heights
+            .iter()
+            .flatten()
+            .fold((f32::MAX, f32::MIN), |(min, max), h| (min.min(*h), max.max(*h)));
+        if max - min <= kind.max_slope() {
+            return Some(StructureSite {
+                origin: IVec3::new(x, max as i32, z),
+                template,
+                heights,
+            });
+        }
+    }
+    None
+}
+
+// 和 [min, max] 范围 (世界方块坐标) 有交集的单元里的建筑
+pub fn sites_in_area(
+    min: IVec2,
+    max: IVec2,
+    map_generator_info: &MapGeneratorInfo,
+) -> Vec<StructureSite> {
+    let mut sites = Vec::new();
+    for cell_x in min.x.div_euclid(CELL_SIZE)..=max.x.div_euclid(CELL_SIZE) {
+        for cell_z in min.y.div_euclid(CELL_SIZE)..=max.y.div_euclid(CELL_SIZE) {
+            sites.extend(site_in_cell(cell_x, cell_z, map_generator_info));
+        }
+    }
+    sites
+}
+
+#[derive(Resource)]
+pub struct StructureAssets {
+    materials: HashMap<StructureBlock, Handle<StandardMaterial>>,
+    props: HashMap<StructureProp, Handle<Scene>>,
+}
+
+// 已经计算过的单元
+#[derive(Resource, Default)]
+pub struct StructureSites(HashMap<(i32, i32), Option<StructureSite>>);
+
+pub fn setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut block_materials = HashMap::new();
+    block_materials.insert(
+        StructureBlock::Stone,
+        materials.add(Color::srgb(0.45, 0.45, 0.47)),
+    );
+    block_materials.insert(
+        StructureBlock::Plank,
+        materials.add(Color::srgb(0.62, 0.45, 0.27)),
+    );
+    block_materials.insert(StructureBlock::Log, materials.add(Color::srgb(0.4, 0.28, 0.16)));
+
+    let mut props = HashMap::new();
+    for prop in [
+        StructureProp::Table,
+        StructureProp::Crate,
+        StructureProp::Chest,
+        StructureProp::Bucket,
+    ] {
+        props.insert(
+            prop,
+            asset_server.load(GltfAssetLabel::Scene(0).from_asset(prop.model())),
+        );
+    }
+
+    commands.insert_resource(StructureAssets {
+        materials: block_materials,
+        props,
+    });
+    commands.insert_resource(StructureSites::default());
+}
+
+// 新加载的区块, 把落在区块内的建筑部分作为子实体挂上去
+pub fn structure_update(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut sites: ResMut<StructureSites>,
+    structure_assets: Res<StructureAssets>,
+    new_region_query: Query<(Entity, &ViewRegion), Added<ViewRegion>>,
+    map_generator_info_query: Query<&MapGeneratorInfo>,
+) {
+    let Ok(map_generator_info) = map_generator_info_query.single() else {
+        return;
+    };
+
+    for (entity, view_region) in new_region_query.iter() {
+        let cell_x = view_region.block_x.div_euclid(CELL_REGIONS);
+        let cell_z = view_region.block_z.div_euclid(CELL_REGIONS);
+        let site = sites
+            .0
+            .entry((cell_x, cell_z))
+            .or_insert_with(|| site_in_cell(cell_x, cell_z, map_generator_info));
+        let Some(site) = site else {
+            continue;
+        };
+
+        let region_min = IVec3::new(
+            view_region.block_x * PLAIN_SIZE,
+            0,
+            view_region.block_z * PLAIN_SIZE,
+        );
+        let in_region = |x: i32, z: i32| {
+            x >= region_min.x
+                && x < region_min.x + PLAIN_SIZE
+                && z >= region_min.z
+                && z < region_min.z + PLAIN_SIZE
+        };
+
+        // 按材质分组合并方块
+        let mut block_groups: HashMap<StructureBlock, Vec<Transform>> = HashMap::new();
+        for (pos, block) in site.world_blocks() {
+            if in_region(pos.x, pos.z) {
+                let local = (pos - region_min).as_vec3();
+                block_groups
+                    .entry(block)
+                    .or_default()
+                    .push(Transform::from_translation(local));
+            }
+        }
+
+        commands.entity(entity).with_children(|parent| {
+            for (block, transforms) in block_groups {
+                let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+                let mesh = (cube * transforms).build();
+                let collider = Collider::from_bevy_mesh(
+                    &mesh,
+                    &ComputedColliderShape::TriMesh(TriMeshFlags::default()),
+                );
+                let mut piece = parent.spawn((
+                    Mesh3d(meshes.add(mesh)),
+                    MeshMaterial3d(structure_assets.materials[&block].clone()),
+                    Transform::default(),
+                ));
+                if let Some(collider) = collider {
+                    piece.insert((RigidBody::Fixed, collider));
+                }
+            }
+
+            // 摆件放在地基顶面上
+            for (pos, yaw, prop) in site.template.props.iter() {
+                let world = site.origin.as_vec3() + *pos + Vec3::new(0.0, 0.5, 0.0);
+                if in_region(world.x.round() as i32, world.z.round() as i32) {
+                    parent.spawn((
+                        SceneRoot(structure_assets.props[prop].clone()),
+                        Transform::from_translation(world - region_min.as_vec3())
+                            .with_rotation(Quat::from_rotation_y(*yaw))
+                            .with_scale(Vec3::splat(prop.scale())),
+                    ));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,481 @@
+use bevy::math::{Ray3d, Vec3};
+use bevy::render::mesh::Mesh;
+use bevy::transform::components::GlobalTransform;
+
+use super::Triangle;
+
+// 叶子节点最多的三角形数
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Bounds {
+    const EMPTY: Bounds = Bounds {
+        min: Vec3::splat(f32::MAX),
+        max: Vec3::splat(f32::MIN),
+    };
+
+    fn grow(&self, p: Vec3) -> Bounds {
+        Bounds {
+            min: self.min.min(p),
+            max: self.max.max(p),
+        }
+    }
+
+    fn overlaps(&self, min: Vec3, max: Vec3) -> bool {
+        self.min.cmple(max).all() && self.max.cmpge(min).all()
+    }
+
+    // 点到盒子的距离平方
+    fn distance_squared(&self, p: Vec3) -> f32 {
+        (p.clamp(self.min, self.max) - p).length_squared()
+    }
+
+    // 射线进入盒子的距离, 不相交时为 None
+    fn ray_enter(&self, origin: Vec3, inv_dir: Vec3, max_distance: f32) -> Option<f32> {
+        let t0 = (self.min - origin) * inv_dir;
+        let t1 = (self.max - origin) * inv_dir;
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element().min(max_distance);
+        (near <= far).then_some(near)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    bounds: Bounds,
+    // 叶子: 三角形在 order 里的起点和数量; 内部节点: count 为 0, start 为右子节点, 左子节点紧跟在后面
+    start: usize,
+    count: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub distance: f32,
+    // 三个顶点的权重, 命中点 = p0 * x + p1 * y + p2 * z
+    pub barycentric: Vec3,
+    // 在 index_list 里的第几个三角形
+    pub triangle: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClosestPoint {
+    pub point: Vec3,
+    pub distance: f32,
+    pub triangle: usize,
+}
+
+// 三角形的层次包围盒, 用于精确拾取和查询
+pub struct Bvh {
+    triangles: Vec<[Vec3; 3]>,
+    order: Vec<usize>,
+    nodes: Vec<BvhNode>,
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<[Vec3; 3]>) -> Bvh {
+        let mut bvh = Bvh {
+            order: (0..triangles.len()).collect(),
+            triangles,
+            nodes: Vec::new(),
+        };
+        if !bvh.triangles.is_empty() {
+            let count = bvh.triangles.len();
+            bvh.build(0, count);
+        }
+        bvh
+    }
+
+    pub fn from_triangle(triangle: &Triangle) -> Bvh {
+        let soup = triangle.unindexed();
+        Bvh::new(
+            soup.points
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect(),
+        )
+    }
+
+    pub fn from_mesh(mesh: &Mesh) -> Bvh {
+        Bvh::from_triangle(&Triangle::from_mesh(mesh))
+    }
+
+    pub fn triangle(&self, index: usize) -> Option<[Vec3; 3]> {
+        self.triangles.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    // 按包围盒最长轴的中位数划分, 返回节点下标
+    fn build(&mut self, start: usize, count: usize) -> usize {
+        let node = self.nodes.len();
+        let slice = &mut self.order[start..start + count];
+        let bounds = slice.iter().fold(Bounds::EMPTY, |b, i| {
+            self.triangles[*i].iter().fold(b, |b, p| b.grow(*p))
+        });
+        self.nodes.push(BvhNode {
+            bounds,
+            start,
+            count,
+        });
+        if count <= LEAF_SIZE {
+            return node;
+        }
+
+        let centroid = |t: &[Vec3; 3]| (t[0] + t[1] + t[2]) / 3.0;
+        let centers = slice
+            .iter()
+            .fold(Bounds::EMPTY, |b, i| b.grow(centroid(&self.triangles[*i])));
+        let size = centers.max - centers.min;
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let half = count / 2;
+        let triangles = &self.triangles;
+        slice.select_nth_unstable_by(half, |a, b| {
+            centroid(&triangles[*a])[axis].total_cmp(&centroid(&triangles[*b])[axis])
+        });
+
+        self.build(start, half);
+        let right = self.build(start + half, count - half);
+        self.nodes[node].start = right;
+        self.nodes[node].count = 0;
+        node
+    }
+
+    // 最近的命中, 正反面都算
+    pub fn ray_cast(&self, ray: Ray3d, max_distance: f32) -> Option<RayHit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let origin = ray.origin;
+        let dir = *ray.direction;
+        let inv_dir = dir.recip();
+        let mut best: Option<RayHit> = None;
+        let mut limit = max_distance;
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.ray_enter(origin, inv_dir, limit).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for i in self.order[node.start..node.start + node.count].iter() {
+                    if let Some((distance, barycentric)) =
+                        ray_triangle(origin, dir, &self.triangles[*i])
+                    {
+                        if distance <= limit {
+                            limit = distance;
+                            best = Some(RayHit {
+                                distance,
+                                barycentric,
+                                triangle: *i,
+                            });
+                        }
+                    }
+                }
+                continue;
+            }
+            // 先访问近的子节点
+            let (left, right) = (index + 1, node.start);
+            let near_left = self.nodes[left].bounds.ray_enter(origin, inv_dir, limit);
+            let near_right = self.nodes[right].bounds.ray_enter(origin, inv_dir, limit);
+            match (near_left, near_right) {
+                (Some(l), Some(r)) if l <= r => stack.extend([right, left]),
+                (Some(_), Some(_)) => stack.extend([left, right]),
+                (Some(_), None) => stack.push(left),
+                (None, Some(_)) => stack.push(right),
+                (None, None) => {}
+            }
+        }
+        best
+    }
+
+    // 世界坐标的射线, transform 为网格实体的 GlobalTransform, 返回的距离也是世界坐标
+    pub fn ray_cast_world(
+        &self,
+        ray: Ray3d,
+        transform: &GlobalTransform,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let inverse = transform.affine().inverse();
+        let local_origin = inverse.transform_point3(ray.origin);
+        let local_dir = inverse.transform_vector3(*ray.direction);
+        let scale = local_dir.length();
+        let local_ray = Ray3d::new(local_origin, bevy::math::Dir3::new(local_dir).ok()?);
+        self.ray_cast(local_ray, max_distance * scale)
+            .map(|hit| RayHit {
+                distance: hit.distance / scale,
+                ..hit
+            })
+    }
+
+    // 和盒子相交的三角形
+    pub fn overlap_aabb(&self, min: Vec3, max: Vec3) -> Vec<usize> {
+        let mut rv = Vec::new();
+        if self.nodes.is_empty() {
+            return rv;
+        }
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.overlaps(min, max) {
+                continue;
+            }
+            if node.count > 0 {
+                rv.extend(
+                    self.order[node.start..node.start + node.count]
+                        .iter()
+                        .filter(|i| triangle_aabb(&self.triangles[**i], min, max)),
+                );
+            } else {
+                stack.extend([index + 1, node.start]);
+            }
+        }
+        rv.sort_unstable();
+        rv
+    }
+
+    // 网格表面上离 p 最近的点
+    pub fn closest_point(&self, p: Vec3) -> Option<ClosestPoint> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut best: Option<ClosestPoint> = None;
+        let mut limit = f32::MAX;
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.distance_squared(p) > limit {
+                continue;
+            }
+            if node.count > 0 {
+                for i in self.order[node.start..node.start + node.count].iter() {
+                    let point = closest_on_triangle(p, &self.triangles[*i]);
+                    let distance = point.distance_squared(p);
+                    if distance < limit {
+                        limit = distance;
+                        best = Some(ClosestPoint {
+                            point,
+                            distance: distance.sqrt(),
+                            triangle: *i,
+                        });
+                    }
+                }
+                continue;
+            }
+            let (left, right) = (index + 1, node.start);
+            if self.nodes[left].bounds.distance_squared(p)
+                <= self.nodes[right].bounds.distance_squared(p)
+            {
+                stack.extend([right, left]);
+            } else {
+                stack.extend([left, right]);
+            }
+        }
+        best
+    }
+}
+
+// Möller–Trumbore, 返回 (距离, 重心坐标)
+pub fn ray_triangle(origin: Vec3, dir: Vec3, t: &[Vec3; 3]) -> Option<(f32, Vec3)> {
+    let e1 = t[1] - t[0];
+    let e2 = t[2] - t[0];
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON * e1.length() * e2.length() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - t[0];
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = e2.dot(q) * inv_det;
+    (distance >= 0.0).then_some((distance, Vec3::new(1.0 - u - v, u, v)))
+}
+
+// 分离轴测试
+pub fn triangle_aabb(t: &[Vec3; 3], min: Vec3, max: Vec3) -> bool {
+    let center = (min + max) * 0.5;
+    let extent = (max - min) * 0.5;
+    let v = t.map(|p| p - center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+
+    let separated = |axis: Vec3| {
+        if axis.length_squared() < f32::EPSILON {
+            return false;
+        }
+        let projected = v.map(|p| p.dot(axis));
+        let r = extent.dot(axis.abs());
+        let lo = projected[0].min(projected[1]).min(projected[2]);
+        let hi = projected[0].max(projected[1]).max(projected[2]);
+        lo > r || hi < -r
+    };
+
+    if [Vec3::X, Vec3::Y, Vec3::Z].into_iter().any(separated) {
+        return false;
+    }
+    if separated(edges[0].cross(edges[1])) {
+        return false;
+    }
+    !edges
+        .iter()
+        .flat_map(|e| [e.cross(Vec3::X), e.cross(Vec3::Y), e.cross(Vec3::Z)])
+        .any(separated)
+}
+
+// 三角形上离 p 最近的点
+pub fn closest_on_triangle(p: Vec3, t: &[Vec3; 3]) -> Vec3 {
+    let (a, b, c) = (t[0], t[1], t[2]);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::Dir3;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_point(rng: &mut StdRng, range: f32) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+        )
+    }
+
+    fn random_triangles(rng: &mut StdRng, count: usize) -> Vec<[Vec3; 3]> {
+        (0..count)
+            .map(|_| {
+                let center = random_point(rng, 10.0);
+                [
+                    center + random_point(rng, 1.0),
+                    center + random_point(rng, 1.0),
+                    center + random_point(rng, 1.0),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ray_cast() {
+        let mut rng = StdRng::seed_from_u64(38);
+        let triangles = random_triangles(&mut rng, 500);
+        let bvh = Bvh::new(triangles.clone());
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = random_point(&mut rng, 15.0);
+            let Ok(dir) = Dir3::new(random_point(&mut rng, 1.0)) else {
+                continue;
+            };
+            let brute = triangles
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| ray_triangle(origin, *dir, t).map(|(d, _)| (d, i)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let hit = bvh.ray_cast(Ray3d::new(origin, dir), f32::MAX);
+            assert_eq!(brute.map(|b| b.0), hit.map(|h| h.distance));
+            if let Some(hit) = hit {
+                hits += 1;
+                // 重心坐标还原命中点
+                let t = triangles[hit.triangle];
+                let point =
+                    t[0] * hit.barycentric.x + t[1] * hit.barycentric.y + t[2] * hit.barycentric.z;
+                assert!(point.distance(origin + *dir * hit.distance) < 1e-3);
+            }
+        }
+        assert!(hits > 20);
+    }
+
+    #[test]
+    fn test_overlap_and_closest() {
+        let mut rng = StdRng::seed_from_u64(138);
+        let triangles = random_triangles(&mut rng, 500);
+        let bvh = Bvh::new(triangles.clone());
+        for _ in 0..200 {
+            let center = random_point(&mut rng, 10.0);
+            let half = Vec3::splat(rng.gen_range(0.1..3.0));
+            let brute: Vec<usize> = (0..triangles.len())
+                .filter(|i| triangle_aabb(&triangles[*i], center - half, center + half))
+                .collect();
+            assert_eq!(bvh.overlap_aabb(center - half, center + half), brute);
+
+            let p = random_point(&mut rng, 15.0);
+            let brute = triangles
+                .iter()
+                .map(|t| closest_on_triangle(p, t).distance(p))
+                .fold(f32::MAX, f32::min);
+            let closest = bvh.closest_point(p).unwrap();
+            assert!((closest.distance - brute).abs() < 1e-5);
+            assert!((closest.point.distance(p) - brute).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_ray_cast_world() {
+        use bevy::prelude::*;
+        let bvh = Bvh::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        let transform =
+            GlobalTransform::from(Transform::from_xyz(5.0, 0.0, 0.0).with_scale(Vec3::splat(2.0)));
+        let ray = Ray3d::new(Vec3::new(0.0, 0.0, 0.0), Dir3::X);
+        let hit = bvh.ray_cast_world(ray, &transform, 100.0).unwrap();
+        // 放大两倍后立方体的左面在 x = 4
+        assert!((hit.distance - 4.0).abs() < 1e-5);
+        assert!(bvh.ray_cast_world(ray, &transform, 3.0).is_none());
+    }
+}
@@ -0,0 +1,466 @@
+use bevy::math::Vec3;
+
+use super::Triangle;
+
+// 点在平面上的容差
+const EPSILON: f32 = 1e-5;
+
+// 顶点记录位置和原网格顶点的混合权重, 切分时一起插值
+#[derive(Clone, Debug)]
+struct CsgVertex {
+    pos: Vec3,
+    weights: Vec<(u32, f32)>,
+}
+
+impl CsgVertex {
+    fn lerp(&self, other: &CsgVertex, t: f32) -> CsgVertex {
+        let mut weights: Vec<(u32, f32)> = self
+            .weights
+            .iter()
+            .map(|(i, w)| (*i, w * (1.0 - t)))
+            .collect();
+        for (i, w) in other.weights.iter() {
+            match weights.iter_mut().find(|(j, _)| j == i) {
+                Some((_, v)) => *v += w * t,
+                None => weights.push((*i, w * t)),
+            }
+        }
+        CsgVertex {
+            pos: self.pos.lerp(other.pos, t),
+            weights,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    normal: Vec3,
+    w: f32,
+}
+
+impl Plane {
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Plane> {
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(Plane {
+            normal,
+            w: normal.dot(a),
+        })
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.w = -self.w;
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Polygon {
+    vertices: Vec<CsgVertex>,
+    plane: Plane,
+    // 法线是否要取反 (差集里 B 的内表面)
+    flipped: bool,
+}
+
+impl Polygon {
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        self.plane.flip();
+        self.flipped = !self.flipped;
+    }
+}
+
+const COPLANAR: u8 = 0;
+const FRONT: u8 = 1;
+const BACK: u8 = 2;
+const SPANNING: u8 = 3;
+
+impl Plane {
+    // 按平面切分多边形, 共面的按朝向放进 coplanar_front / coplanar_back
+    fn split_polygon(
+        &self,
+        polygon: Polygon,
+        coplanar_front: &mut Vec<Polygon>,
+        coplanar_back: &mut Vec<Polygon>,
+        front: &mut Vec<Polygon>,
+        back: &mut Vec<Polygon>,
+    ) {
+        let types: Vec<u8> = polygon
+            .vertices
+            .iter()
+            .map(|v| {
+                let t = self.normal.dot(v.pos) - self.w;
+                if t < -EPSILON {
+                    BACK
+                } else if t > EPSILON {
+                    FRONT
+                } else {
+                    COPLANAR
+                }
+            })
+            .collect();
+        let polygon_type = types.iter().fold(COPLANAR, |a, b| a | b);
+
+        match polygon_type {
+            COPLANAR => {
+                if self.normal.dot(polygon.plane.normal) > 0.0 {
+                    coplanar_front.push(polygon);
+                } else {
+                    coplanar_back.push(polygon);
+                }
+            }
+            FRONT => front.push(polygon),
+            BACK => back.push(polygon),
+            _ => {
+                let mut f: Vec<CsgVertex> = Vec::new();
+                let mut b: Vec<CsgVertex> = Vec::new();
+                let count = polygon.vertices.len();
+                for i in 0..count {
+                    let j = (i + 1) % count;
+                    let (ti, tj) = (types[i], types[j]);
+                    let (vi, vj) = (&polygon.vertices[i], &polygon.vertices[j]);
+                    if ti != BACK {
+                        f.push(vi.clone());
+                    }
+                    if ti != FRONT {
+                        b.push(vi.clone());
+                    }
+                    if (ti | tj) == SPANNING {
+                        let t =
+                            (self.w - self.normal.dot(vi.pos)) / self.normal.dot(vj.pos - vi.pos);
+                        let v = vi.lerp(vj, t);
+                        f.push(v.clone());
+                        b.push(v);
+                    }
+                }
+                if f.len() >= 3 {
+                    front.push(Polygon {
+                        vertices: f,
+                        ..polygon.clone()
+                    });
+                }
+                if b.len() >= 3 {
+                    back.push(Polygon {
+                        vertices: b,
+                        ..polygon
+                    });
+                }
+            }
+        }
+    }
+}
+
+// BSP 树, 每个节点保存和分割面共面的多边形
+#[derive(Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+    polygons: Vec<Polygon>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Node {
+        let mut node = Node::default();
+        node.build(polygons);
+        node
+    }
+
+    // 内外互换
+    fn invert(&mut self) {
+        self.polygons.iter_mut().for_each(|p| p.flip());
+        if let Some(plane) = self.plane.as_mut() {
+            plane.flip();
+        }
+        if let Some(front) = self.front.as_mut() {
+            front.invert();
+        }
+        if let Some(back) = self.back.as_mut() {
+            back.invert();
+        }
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    // 去掉在这棵树实体内部的多边形
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let Some(plane) = self.plane else {
+            return polygons;
+        };
+        let mut front: Vec<Polygon> = Vec::new();
+        let mut back: Vec<Polygon> = Vec::new();
+        for polygon in polygons {
+            let (mut coplanar_front, mut coplanar_back) = (Vec::new(), Vec::new());
+            plane.split_polygon(
+                polygon,
+                &mut coplanar_front,
+                &mut coplanar_back,
+                &mut front,
+                &mut back,
+            );
+            front.extend(coplanar_front);
+            back.extend(coplanar_back);
+        }
+        let mut front = match &self.front {
+            Some(node) => node.clip_polygons(front),
+            None => front,
+        };
+        let back = match &self.back {
+            Some(node) => node.clip_polygons(back),
+            None => vec![],
+        };
+        front.extend(back);
+        front
+    }
+
+    fn clip_to(&mut self, other: &Node) {
+        self.polygons = other.clip_polygons(std::mem::take(&mut self.polygons));
+        if let Some(front) = self.front.as_mut() {
+            front.clip_to(other);
+        }
+        if let Some(back) = self.back.as_mut() {
+            back.clip_to(other);
+        }
+    }
+
+    fn all_polygons(&self) -> Vec<Polygon> {
+        let mut rv = self.polygons.clone();
+        if let Some(front) = &self.front {
+            rv.extend(front.all_polygons());
+        }
+        if let Some(back) = &self.back {
+            rv.extend(back.all_polygons());
+        }
+        rv
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        let plane = *self.plane.get_or_insert(polygons[0].plane);
+        let mut front: Vec<Polygon> = Vec::new();
+        let mut back: Vec<Polygon> = Vec::new();
+        for polygon in polygons {
+            let (mut coplanar_front, mut coplanar_back) = (Vec::new(), Vec::new());
+            plane.split_polygon(
+                polygon,
+                &mut coplanar_front,
+                &mut coplanar_back,
+                &mut front,
+                &mut back,
+            );
+            self.polygons.extend(coplanar_front);
+            self.polygons.extend(coplanar_back);
+        }
+        if !front.is_empty() {
+            self.front.get_or_insert_with(Box::default).build(front);
+        }
+        if !back.is_empty() {
+            self.back.get_or_insert_with(Box::default).build(back);
+        }
+    }
+}
+
+enum Operation {
+    Union,
+    Difference,
+    Intersection,
+}
+
+// 顶点索引从 offset 开始
+fn to_polygons(triangle: &Triangle, offset: u32) -> Vec<Polygon> {
+    triangle
+        .index_list()
+        .chunks_exact(3)
+        .filter_map(|tri| {
+            let vertices: Vec<CsgVertex> = tri
+                .iter()
+                .map(|i| CsgVertex {
+                    pos: triangle.points[*i as usize],
+                    weights: vec![(*i + offset, 1.0)],
+                })
+                .collect();
+            let plane = Plane::from_points(vertices[0].pos, vertices[1].pos, vertices[2].pos)?;
+            Some(Polygon {
+                vertices,
+                plane,
+                flipped: false,
+            })
+        })
+        .collect()
+}
+
+fn csg(a: &Triangle, b: &Triangle, operation: Operation) -> Triangle {
+    // a 和 b 的顶点放在一起, 新顶点的属性从这里混合
+    let mut pool = a.clone();
+    pool.append(b.clone());
+
+    let mut a_node = Node::new(to_polygons(a, 0));
+    let mut b_node = Node::new(to_polygons(b, a.points.len() as u32));
+    match operation {
+        Operation::Union => {
+            a_node.clip_to(&b_node);
+            b_node.clip_to(&a_node);
+            b_node.invert();
+            b_node.clip_to(&a_node);
+            b_node.invert();
+            a_node.build(b_node.all_polygons());
+        }
+        Operation::Difference => {
+            a_node.invert();
+            a_node.clip_to(&b_node);
+            b_node.clip_to(&a_node);
+            b_node.invert();
+            b_node.clip_to(&a_node);
+            b_node.invert();
+            a_node.build(b_node.all_polygons());
+            a_node.invert();
+        }
+        Operation::Intersection => {
+            a_node.invert();
+            b_node.clip_to(&a_node);
+            b_node.invert();
+            a_node.clip_to(&b_node);
+            b_node.clip_to(&a_node);
+            a_node.build(b_node.all_polygons());
+            a_node.invert();
+        }
+    }
+
+    // 多边形按扇形拆成三角形
+    let mut corners: Vec<Vec<(u32, f32)>> = Vec::new();
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut flipped: Vec<bool> = Vec::new();
+    for polygon in a_node.all_polygons() {
+        for i in 1..polygon.vertices.len() - 1 {
+            for v in [
+                &polygon.vertices[0],
+                &polygon.vertices[i],
+                &polygon.vertices[i + 1],
+            ] {
+                corners.push(v.weights.clone());
+                positions.push(v.pos);
+                flipped.push(polygon.flipped);
+            }
+        }
+    }
+    let mut r = pool.blend(&corners);
+    r.points = positions;
+    for (i, flip) in flipped.iter().enumerate() {
+        if !*flip {
+            continue;
+        }
+        if let Some(n) = r.normal.get_mut(i) {
+            *n = -*n;
+        }
+        if let Some(t) = r.tangent.get_mut(i) {
+            t.w = -t.w;
+        }
+    }
+    r.weld()
+}
+
+impl Triangle {
+    // 以下布尔运算要求两个网格都是闭合的, 三角形朝外
+
+    pub fn union(&self, other: &Triangle) -> Triangle {
+        csg(self, other, Operation::Union)
+    }
+
+    pub fn difference(&self, other: &Triangle) -> Triangle {
+        csg(self, other, Operation::Difference)
+    }
+
+    pub fn intersection(&self, other: &Triangle) -> Triangle {
+        csg(self, other, Operation::Intersection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::*;
+
+    // 散度定理求体积
+    fn volume(t: &Triangle) -> f32 {
+        t.unindexed()
+            .points
+            .chunks_exact(3)
+            .map(|p| p[0].dot(p[1].cross(p[2])) / 6.0)
+            .sum()
+    }
+
+    fn check(t: &Triangle) {
+        let soup = t.unindexed();
+        for (n, face) in soup.normal.chunks_exact(3).zip(soup.face_normals()) {
+            // 立方体没有曲面, 顶点法线等于面法线
+            for n in n {
+                assert!(n.dot(face) > 0.999, "{n} {face}");
+            }
+        }
+        for uv in soup.uv.iter() {
+            assert!(uv.x >= -1e-5 && uv.x <= 1.0 + 1e-5 && uv.y >= -1e-5 && uv.y <= 1.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_csg() {
+        let a = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        let b = a.clone() * Transform::from_xyz(0.5, 0.25, 0.0);
+
+        let union = a.union(&b);
+        assert!((volume(&union) - 1.625).abs() < 1e-4, "{}", volume(&union));
+        check(&union);
+
+        let difference = a.difference(&b);
+        assert!(
+            (volume(&difference) - 0.625).abs() < 1e-4,
+            "{}",
+            volume(&difference)
+        );
+        check(&difference);
+
+        let intersection = a.intersection(&b);
+        assert!(
+            (volume(&intersection) - 0.375).abs() < 1e-4,
+            "{}",
+            volume(&intersection)
+        );
+        check(&intersection);
+
+        // 交集的包围盒
+        let (min, max) = intersection
+            .points
+            .iter()
+            .fold((Vec3::MAX, Vec3::MIN), |(a, b), p| (a.min(*p), b.max(*p)));
+        assert!(min.distance(Vec3::new(0.0, -0.25, -0.5)) < 1e-5);
+        assert!(max.distance(Vec3::new(0.5, 0.5, 0.5)) < 1e-5);
+
+        // 切出来的新顶点 uv 是插值的: 差集里 +z 面只来自 a, uv 和 (x, y) 是同一个仿射关系
+        let uv_at = |t: &Triangle, x: f32, y: f32| {
+            t.points
+                .iter()
+                .zip(t.normal.iter())
+                .position(|(p, n)| *n == Vec3::Z && p.x == x && p.y == y)
+                .map(|i| t.uv[i])
+                .unwrap()
+        };
+        let uv00 = uv_at(&a, -0.5, -0.5);
+        let uv10 = uv_at(&a, 0.5, -0.5);
+        let uv01 = uv_at(&a, -0.5, 0.5);
+        let soup = difference.unindexed();
+        let mut checked = 0;
+        for ((p, n), uv) in soup
+            .points
+            .iter()
+            .zip(soup.normal.iter())
+            .zip(soup.uv.iter())
+        {
+            if n.dot(Vec3::Z) > 0.999 {
+                let expect = uv00 + (p.x + 0.5) * (uv10 - uv00) + (p.y + 0.5) * (uv01 - uv00);
+                assert!(uv.distance(expect) < 1e-5, "{p} {uv} {expect}");
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+}
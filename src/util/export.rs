@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::math::Vec3;
+use bevy::transform::components::Transform;
+
+use super::Triangle;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportMaterial {
+    pub name: String,
+    // srgb
+    pub base_color: [f32; 4],
+    // 贴图路径, 相对于 mtl 文件, 只写进 mtl
+    pub texture: Option<String>,
+}
+
+impl Default for ExportMaterial {
+    fn default() -> Self {
+        ExportMaterial {
+            name: "default".to_string(),
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            texture: None,
+        }
+    }
+}
+
+// 要导出的一个网格, 顶点会先乘上 transform
+pub struct ExportMesh {
+    pub name: String,
+    pub triangle: Triangle,
+    pub transform: Transform,
+    pub material: ExportMaterial,
+}
+
+impl ExportMesh {
+    pub fn new(name: impl Into<String>, triangle: Triangle) -> ExportMesh {
+        ExportMesh {
+            name: name.into(),
+            triangle,
+            transform: Transform::IDENTITY,
+            material: ExportMaterial::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> ExportMesh {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_material(mut self, material: ExportMaterial) -> ExportMesh {
+        self.material = material;
+        self
+    }
+
+    fn baked(&self) -> Triangle {
+        self.triangle.clone() * self.transform
+    }
+}
+
+// 同名材质只保留第一个
+fn unique_materials(meshes: &[ExportMesh]) -> Vec<&ExportMaterial> {
+    let mut rv: Vec<&ExportMaterial> = Vec::new();
+    for mesh in meshes {
+        if !rv.iter().any(|m| m.name == mesh.material.name) {
+            rv.push(&mesh.material);
+        }
+    }
+    rv
+}
+
+// Wavefront OBJ, mtl_name 为 mtllib 引用的文件名
+pub fn obj_string(meshes: &[ExportMesh], mtl_name: &str) -> String {
+    let mut obj = String::new();
+    let _ = writeln!(obj, "mtllib {}", mtl_name);
+    // obj 的索引从 1 开始, 并且在所有对象间累计
+    let (mut v_offset, mut vt_offset, mut vn_offset) = (1usize, 1usize, 1usize);
+    for mesh in meshes {
+        let tri = mesh.baked();
+        let _ = writeln!(obj, "o {}", mesh.name);
+        for p in tri.points.iter() {
+            let _ = writeln!(obj, "v {} {} {}", p.x, p.y, p.z);
+        }
+        // 和顶点数量不一致的 uv / 法线不导出, 免得面的索引对不上
+        let has_uv = tri.uv.len() == tri.points.len();
+        let has_normal = tri.normal.len() == tri.points.len();
+        // obj 的 v 轴向上, bevy 的 uv 原点在左上
+        if has_uv {
+            for uv in tri.uv.iter() {
+                let _ = writeln!(obj, "vt {} {}", uv.x, 1.0 - uv.y);
+            }
+        }
+        if has_normal {
+            for n in tri.normal.iter() {
+                let _ = writeln!(obj, "vn {} {} {}", n.x, n.y, n.z);
+            }
+        }
+        let _ = writeln!(obj, "usemtl {}", mesh.material.name);
+        for face in tri.index_list().chunks_exact(3) {
+            obj.push('f');
+            for i in face.iter().map(|i| *i as usize) {
+                match (has_uv, has_normal) {
+                    (true, true) => {
+                        write!(obj, " {}/{}/{}", i + v_offset, i + vt_offset, i + vn_offset)
+                    }
+                    (true, false) => write!(obj, " {}/{}", i + v_offset, i + vt_offset),
+                    (false, true) => write!(obj, " {}//{}", i + v_offset, i + vn_offset),
+                    (false, false) => write!(obj, " {}", i + v_offset),
+                }
+                .ok();
+            }
+            obj.push('\n');
+        }
+        v_offset += tri.points.len();
+        if has_uv {
+            vt_offset += tri.uv.len();
+        }
+        if has_normal {
+            vn_offset += tri.normal.len();
+        }
+    }
+    obj
+}
+
+pub fn mtl_string(meshes: &[ExportMesh]) -> String {
+    let mut mtl = String::new();
+    for material in unique_materials(meshes) {
+        let [r, g, b, a] = material.base_color;
+        let _ = writeln!(mtl, "newmtl {}", material.name);
+        let _ = writeln!(mtl, "Kd {} {} {}", r, g, b);
+        let _ = writeln!(mtl, "d {}", a);
+        if let Some(texture) = &material.texture {
+            let _ = writeln!(mtl, "map_Kd {}", texture);
+        }
+        mtl.push('\n');
+    }
+    mtl
+}
+
+// 写出 path (.obj) 和同名的 .mtl
+pub fn export_obj(path: impl AsRef<Path>, meshes: &[ExportMesh]) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::write(path, obj_string(meshes, &mtl_name))?;
+    fs::write(mtl_path, mtl_string(meshes))
+}
+
+fn json_string(s: &str) -> String {
+    let mut rv = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => rv.push_str("\\\""),
+            '\\' => rv.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(rv, "\\u{:04x}", c as u32);
+            }
+            c => rv.push(c),
+        }
+    }
+    rv.push('"');
+    rv
+}
+
+// 二进制 glTF (glb), 变换已经烘焙进顶点, 材质只导出颜色.
+// 没有三角形的网格跳过, 全部为空时返回错误 (glTF 不允许空的 buffer)
+pub fn glb_bytes(meshes: &[ExportMesh]) -> io::Result<Vec<u8>> {
+    let materials = unique_materials(meshes);
+    let material_index: HashMap<&str, usize> = materials
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.name.as_str(), i))
+        .collect();
+
+    let mut bin: Vec<u8> = Vec::new();
+    let mut buffer_views: Vec<String> = Vec::new();
+    let mut accessors: Vec<String> = Vec::new();
+    let mut gltf_meshes: Vec<String> = Vec::new();
+    let mut nodes: Vec<String> = Vec::new();
+
+    // 数据都是 4 字节, 不需要额外对齐
+    let mut push_view = |bin: &mut Vec<u8>, data: &[u8], target: u32| -> usize {
+        buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            bin.len(),
+            data.len(),
+            target
+        ));
+        bin.extend_from_slice(data);
+        buffer_views.len() - 1
+    };
+
+    for mesh in meshes {
+        let tri = mesh.baked();
+        let indices = tri.index_list();
+        if tri.points.is_empty() || indices.is_empty() {
+            continue;
+        }
+        let vertex_count = tri.points.len();
+        let mut attributes: Vec<String> = Vec::new();
+
+        let floats = |vs: &mut dyn Iterator<Item = f32>| -> Vec<u8> {
+            vs.flat_map(|v| v.to_le_bytes()).collect()
+        };
+
+        // POSITION 必须写 min/max
+        let (min, max) = tri
+            .points
+            .iter()
+            .fold((Vec3::MAX, Vec3::MIN), |(a, b), p| (a.min(*p), b.max(*p)));
+        let view = push_view(
+            &mut bin,
+            &floats(&mut tri.points.iter().flat_map(|p| p.to_array())),
+            34962,
+        );
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+            view, vertex_count, min.x, min.y, min.z, max.x, max.y, max.z
+        ));
+        attributes.push(format!(r#""POSITION":{}"#, accessors.len() - 1));
+
+        if tri.normal.len() == vertex_count {
+            let view = push_view(
+                &mut bin,
+                &floats(&mut tri.normal.iter().flat_map(|n| n.to_array())),
+                34962,
+            );
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3"}}"#,
+                view, vertex_count
+            ));
+            attributes.push(format!(r#""NORMAL":{}"#, accessors.len() - 1));
+        }
+        if tri.uv.len() == vertex_count {
+            let view = push_view(
+                &mut bin,
+                &floats(&mut tri.uv.iter().flat_map(|uv| [uv.x, uv.y])),
+                34962,
+            );
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC2"}}"#,
+                view, vertex_count
+            ));
+            attributes.push(format!(r#""TEXCOORD_0":{}"#, accessors.len() - 1));
+        }
+
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = push_view(&mut bin, &bytes, 34963);
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+            view,
+            indices.len()
+        ));
+
+        gltf_meshes.push(format!(
+            r#"{{"name":{},"primitives":[{{"attributes":{{{}}},"indices":{},"material":{}}}]}}"#,
+            json_string(&mesh.name),
+            attributes.join(","),
+            accessors.len() - 1,
+            material_index[mesh.material.name.as_str()]
+        ));
+        nodes.push(format!(
+            r#"{{"name":{},"mesh":{}}}"#,
+            json_string(&mesh.name),
+            gltf_meshes.len() - 1
+        ));
+    }
+
+    if gltf_meshes.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no triangles to export",
+        ));
+    }
+
+    let gltf_materials: Vec<String> = materials
+        .iter()
+        .map(|m| {
+            let [r, g, b, a] = m.base_color;
+            // glTF 的颜色系数是线性空间
+            let linear = |c: f32| {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            };
+            format!(
+                r#"{{"name":{},"pbrMetallicRoughness":{{"baseColorFactor":[{},{},{},{}],"metallicFactor":0.0,"roughnessFactor":1.0}}}}"#,
+                json_string(&m.name),
+                linear(r),
+                linear(g),
+                linear(b),
+                a
+            )
+        })
+        .collect();
+
+    let scene_nodes: Vec<String> = (0..nodes.len()).map(|i| i.to_string()).collect();
+    let mut json = format!(
+        r#"{{"asset":{{"version":"2.0","generator":"cube-world"}},"scene":0,"scenes":[{{"nodes":[{}]}}],"nodes":[{}],"meshes":[{}],"materials":[{}],"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#,
+        scene_nodes.join(","),
+        nodes.join(","),
+        gltf_meshes.join(","),
+        gltf_materials.join(","),
+        accessors.join(","),
+        buffer_views.join(","),
+        bin.len()
+    )
+    .into_bytes();
+
+    // 块长度按 4 字节对齐, json 用空格补齐, bin 用 0 补齐
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(total);
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(total as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&bin);
+    Ok(glb)
+}
+
+pub fn export_glb(path: impl AsRef<Path>, meshes: &[ExportMesh]) -> io::Result<()> {
+    let path = path.as_ref();
+    let glb = glb_bytes(meshes)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::*;
+
+    fn cubes() -> Vec<ExportMesh> {
+        let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        vec![
+            ExportMesh::new("a", cube.clone()),
+            ExportMesh::new("b", cube)
+                .with_transform(Transform::from_xyz(2.0, 0.0, 0.0))
+                .with_material(ExportMaterial {
+                    name: "red".to_string(),
+                    base_color: [1.0, 0.0, 0.0, 1.0],
+                    texture: None,
+                }),
+        ]
+    }
+
+    #[test]
+    fn test_obj() {
+        let obj = obj_string(&cubes(), "cubes.mtl");
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("v "), 48);
+        assert_eq!(count("vt "), 48);
+        assert_eq!(count("vn "), 48);
+        assert_eq!(count("f "), 24);
+        // 第二个对象的索引接着第一个
+        let last = obj
+            .lines()
+            .filter(|l| l.starts_with("f "))
+            .next_back()
+            .unwrap();
+        assert!(last
+            .split_whitespace()
+            .skip(1)
+            .all(|v| (25..=48).contains(&v.split('/').next().unwrap().parse::<usize>().unwrap())));
+        assert!(obj.contains("v 2.5 "));
+
+        let mtl = mtl_string(&cubes());
+        assert!(mtl.contains("newmtl default"));
+        assert!(mtl.contains("newmtl red\nKd 1 0 0"));
+    }
+
+    #[test]
+    fn test_glb() {
+        let glb = glb_bytes(&cubes()).unwrap();
+        assert_eq!(&glb[0..4], b"glTF");
+        let u32_at = |i: usize| u32::from_le_bytes(glb[i..i + 4].try_into().unwrap()) as usize;
+        assert_eq!(u32_at(8), glb.len());
+        let json_len = u32_at(12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_len % 4, 0);
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""POSITION":0"#));
+        assert!(json.contains(r#""name":"red""#));
+        let bin_len = u32_at(20 + json_len);
+        assert_eq!(&glb[24 + json_len..28 + json_len], b"BIN\0");
+        // 每个立方体: 24 个顶点 * (12 + 12 + 8) 字节 + 36 个索引 * 4 字节
+        assert_eq!(bin_len, 2 * (24 * 32 + 36 * 4));
+    }
+
+    #[test]
+    fn test_mismatched_attributes() {
+        // 第一个立方体少一个法线, 没有 uv
+        let mut cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        cube.normal.pop();
+        cube.uv.clear();
+        let mut meshes = cubes();
+        meshes[0].triangle = cube;
+
+        let obj = obj_string(&meshes, "cubes.mtl");
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("vt "), 24);
+        assert_eq!(count("vn "), 24);
+        let faces: Vec<&str> = obj.lines().filter(|l| l.starts_with("f ")).collect();
+        assert!(!faces[0].contains('/'));
+        // 第二个对象的 vt / vn 从 1 开始
+        assert!(faces[12].split_whitespace().skip(1).all(|v| v
+            .split('/')
+            .skip(1)
+            .all(|i| (1..=24).contains(&i.parse().unwrap()))));
+
+        let glb = glb_bytes(&meshes).unwrap();
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert_eq!(json.matches("NORMAL").count(), 1);
+        assert_eq!(json.matches("TEXCOORD_0").count(), 1);
+    }
+
+    #[test]
+    fn test_empty_glb() {
+        assert!(glb_bytes(&[]).is_err());
+        let empty = ExportMesh::new("empty", Triangle::new(vec![], vec![], vec![]));
+        assert!(glb_bytes(&[empty]).is_err());
+
+        // 空网格跳过, 其他照常导出
+        let mut meshes = cubes();
+        meshes.push(ExportMesh::new(
+            "empty",
+            Triangle::new(vec![], vec![], vec![]),
+        ));
+        let glb = glb_bytes(&meshes).unwrap();
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(!json.contains(r#""name":"empty""#));
+        assert!(!json.contains(r#""count":0"#));
+    }
+}
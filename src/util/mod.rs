@@ -0,0 +1,1188 @@
+use bevy::math::Vec3;
+use bevy::prelude::*;
+use bevy::render::mesh::{GenerateTangentsError, MeshVertexAttribute, VertexAttributeValues};
+use bevy::render::{
+    mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology,
+};
+use bevy::tasks::futures_lite::io::split;
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
+
+pub mod bvh;
+pub mod csg;
+pub mod export;
+pub mod simplify;
+
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub points: Vec<Vec3>,
+    pub normal: Vec<Vec3>,
+    pub uv: Vec<Vec3>, // 最后一位是0
+    // w 为副切线方向 (1 或 -1), 可以为空
+    pub tangent: Vec<Vec4>,
+    // 其它顶点属性: 颜色, uv_1, 骨骼索引和权重等, 原样保留
+    pub attributes: Vec<(MeshVertexAttribute, VertexAttributeValues)>,
+    // None 时每三个顶点一个三角形, Some 时按索引取顶点
+    pub indices: Option<Vec<u32>>,
+}
+
+impl Triangle {
+    pub fn new(points: Vec<Vec3>, normal: Vec<Vec3>, uv: Vec<Vec3>) -> Triangle {
+        Triangle {
+            points: points,
+            normal: normal,
+            uv: uv,
+            tangent: vec![],
+            attributes: vec![],
+            indices: None,
+        }
+    }
+
+    pub fn new_indexed(
+        points: Vec<Vec3>,
+        normal: Vec<Vec3>,
+        uv: Vec<Vec3>,
+        indices: Vec<u32>,
+    ) -> Triangle {
+        Triangle {
+            points: points,
+            normal: normal,
+            uv: uv,
+            tangent: vec![],
+            attributes: vec![],
+            indices: Some(indices),
+        }
+    }
+
+    // 三角形的顶点索引, 没有索引时为 0..顶点数
+    pub fn index_list(&self) -> Vec<u32> {
+        match &self.indices {
+            Some(indices) => indices.clone(),
+            None => (0..self.points.len() as u32).collect(),
+        }
+    }
+
+    // 展开成不带索引的三角形列表
+    pub fn unindexed(&self) -> Triangle {
+        let Some(indices) = &self.indices else {
+            return self.clone();
+        };
+        fn pick<T: Copy + Default>(vs: &[T], indices: &[u32]) -> Vec<T> {
+            if vs.is_empty() {
+                return vec![];
+            }
+            indices
+                .iter()
+                .map(|i| vs.get(*i as usize).copied().unwrap_or_default())
+                .collect()
+        }
+        let mut r = Triangle::new(
+            pick(&self.points, indices),
+            pick(&self.normal, indices),
+            pick(&self.uv, indices),
+        );
+        r.tangent = pick(&self.tangent, indices);
+        r.attributes = self
+            .attributes
+            .iter()
+            .map(|(attribute, values)| (attribute.clone(), values_pick(values, indices)))
+            .collect();
+        r
+    }
+
+    // 合并所有属性都相同的顶点, 生成带索引的三角形
+    pub fn weld(&self) -> Triangle {
+        let key = |v: Option<&Vec3>| -> [u32; 3] {
+            // +0.0 把 -0.0 变成 0.0
+            v.map_or([0; 3], |v| {
+                [
+                    (v.x + 0.0).to_bits(),
+                    (v.y + 0.0).to_bits(),
+                    (v.z + 0.0).to_bits(),
+                ]
+            })
+        };
+
+        // 其它属性按字节比较
+        let extra: Vec<(&[u8], usize)> = self
+            .attributes
+            .iter()
+            .filter_map(|(_, values)| {
+                let bytes = values.get_bytes();
+                bytes
+                    .len()
+                    .checked_div(values.len())
+                    .map(|stride| (bytes, stride))
+            })
+            .collect();
+
+        let mut lookup: HashMap<([u32; 3], [u32; 3], [u32; 3], [u32; 4], Vec<u8>), u32> =
+            HashMap::new();
+        let mut points = Vec::new();
+        let mut normal = Vec::new();
+        let mut uv = Vec::new();
+        let mut tangent = Vec::new();
+        let mut kept = Vec::new();
+        let mut indices = Vec::new();
+        for i in self.index_list() {
+            let i = i as usize;
+            let vertex_key = (
+                key(self.points.get(i)),
+                key(self.normal.get(i)),
+                key(self.uv.get(i)),
+                self.tangent
+                    .get(i)
+                    .map_or([0; 4], |t| t.to_array().map(|v| (v + 0.0).to_bits())),
+                extra
+                    .iter()
+                    .flat_map(|(bytes, stride)| {
+                        bytes.get(i * stride..(i + 1) * stride).unwrap_or(&[])
+                    })
+                    .copied()
+                    .collect(),
+            );
+            let index = *lookup.entry(vertex_key).or_insert_with(|| {
+                points.push(*self.points.get(i).unwrap_or(&Vec3::ZERO));
+                if let Some(n) = self.normal.get(i) {
+                    normal.push(*n);
+                }
+                if let Some(t) = self.uv.get(i) {
+                    uv.push(*t);
+                }
+                if let Some(t) = self.tangent.get(i) {
+                    tangent.push(*t);
+                }
+                kept.push(i as u32);
+                points.len() as u32 - 1
+            });
+            indices.push(index);
+        }
+        let mut r = Triangle::new_indexed(points, normal, uv, indices);
+        r.tangent = tangent;
+        r.attributes = self
+            .attributes
+            .iter()
+            .map(|(attribute, values)| (attribute.clone(), values_pick(values, &kept)))
+            .collect();
+        r
+    }
+
+    // 每个三角形的面法线, 退化的三角形为 0
+    pub fn face_normals(&self) -> Vec<Vec3> {
+        let soup = self.unindexed();
+        soup.points
+            .chunks_exact(3)
+            .map(|p| (p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero())
+            .collect()
+    }
+
+    // 硬边法线: 每个顶点取所在三角形的面法线, 结果不带索引
+    pub fn flat_normals(&self) -> Triangle {
+        let mut soup = self.unindexed();
+        soup.normal = self
+            .face_normals()
+            .into_iter()
+            .flat_map(|n| [n, n, n])
+            .collect();
+        soup
+    }
+
+    // 平滑法线: 同一位置的顶点按角度加权平均相邻面的法线,
+    // 和当前面夹角超过 crease_angle (弧度) 的面不参与, 保留硬边
+    pub fn smooth_normals(&self, crease_angle: f32) -> Triangle {
+        let mut soup = self.unindexed();
+        let face_normals = soup.face_normals();
+        let cos_crease = crease_angle.cos();
+
+        // 每个顶点的角度权重
+        let angles: Vec<f32> = soup
+            .points
+            .chunks_exact(3)
+            .flat_map(|p| {
+                let angle = |a: Vec3, b: Vec3, c: Vec3| (b - a).angle_between(c - a);
+                [
+                    angle(p[0], p[1], p[2]),
+                    angle(p[1], p[2], p[0]),
+                    angle(p[2], p[0], p[1]),
+                ]
+            })
+            .map(|a| if a.is_finite() { a } else { 0.0 })
+            .collect();
+
+        // 按位置分组
+        let mut groups: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+        for (i, p) in soup.points.iter().enumerate().take(face_normals.len() * 3) {
+            groups
+                .entry(p.to_array().map(|v| (v + 0.0).to_bits()))
+                .or_default()
+                .push(i);
+        }
+
+        soup.normal = (0..face_normals.len() * 3)
+            .map(|i| {
+                let face_normal = face_normals[i / 3];
+                let key = soup.points[i].to_array().map(|v| (v + 0.0).to_bits());
+                let sum: Vec3 = groups[&key]
+                    .iter()
+                    .filter(|j| face_normals[*j / 3].dot(face_normal) >= cos_crease)
+                    .map(|j| face_normals[j / 3] * angles[*j])
+                    .sum();
+                sum.try_normalize().unwrap_or(face_normal)
+            })
+            .collect();
+        soup.weld()
+    }
+
+    // 用 MikkTSpace 生成切线, 需要法线和 uv, 失败时不修改
+    pub fn generate_tangents(&mut self) -> Result<(), GenerateTangentsError> {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD,
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            self.points.iter().map(|v| v.to_array()).collect::<Vec<_>>(),
+        )
+        .with_inserted_indices(Indices::U32(self.index_list()));
+        if !self.normal.is_empty() {
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                self.normal.iter().map(|v| v.to_array()).collect::<Vec<_>>(),
+            );
+        }
+        if !self.uv.is_empty() {
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_UV_0,
+                self.uv.iter().map(|v| [v.x, v.y]).collect::<Vec<_>>(),
+            );
+        }
+        mesh.generate_tangents()?;
+        if let Some(VertexAttributeValues::Float32x4(vs)) = mesh.attribute(Mesh::ATTRIBUTE_TANGENT)
+        {
+            self.tangent = vs.iter().map(|v| Vec4::from_array(*v)).collect();
+        }
+        Ok(())
+    }
+
+    // 保留网格的索引, 没有索引时按三角形列表读取
+    pub fn from_mesh(mesh: &Mesh) -> Triangle {
+        let vec3s = |attribute| -> Vec<Vec3> {
+            match mesh.attribute(attribute) {
+                Option::Some(VertexAttributeValues::Float32x3(vs)) => {
+                    vs.iter().map(|v| Vec3::new(v[0], v[1], v[2])).collect()
+                }
+                _ => vec![],
+            }
+        };
+
+        // points
+        let points = vec3s(Mesh::ATTRIBUTE_POSITION);
+
+        // normals
+        let normals = vec3s(Mesh::ATTRIBUTE_NORMAL);
+
+        // uv
+        let uv0s = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Option::Some(VertexAttributeValues::Float32x2(vs)) => {
+                vs.iter().map(|v| Vec3::new(v[0], v[1], 0.0)).collect()
+            }
+            _ => vec![],
+        };
+
+        // tangent
+        let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+            Option::Some(VertexAttributeValues::Float32x4(vs)) => {
+                vs.iter().map(|v| Vec4::from_array(*v)).collect()
+            }
+            _ => vec![],
+        };
+
+        // 其它属性
+        let attributes = mesh
+            .attributes()
+            .filter(|(attribute, _)| {
+                ![
+                    Mesh::ATTRIBUTE_POSITION.id,
+                    Mesh::ATTRIBUTE_NORMAL.id,
+                    Mesh::ATTRIBUTE_UV_0.id,
+                    Mesh::ATTRIBUTE_TANGENT.id,
+                ]
+                .contains(&attribute.id)
+            })
+            .map(|(attribute, values)| (attribute.clone(), values.clone()))
+            .collect();
+
+        Triangle {
+            points: points,
+            normal: normals,
+            uv: uv0s,
+            tangent: tangents,
+            attributes: attributes,
+            indices: mesh
+                .indices()
+                .map(|indices| indices.iter().map(|i| i as u32).collect()),
+        }
+    }
+
+    // 按权重混合原有顶点生成新的三角形列表, 每个新顶点是若干 (顶点索引, 权重)
+    fn blend(&self, corners: &[Vec<(u32, f32)>]) -> Triangle {
+        fn mix<T: Blend>(vs: &[T], corners: &[Vec<(u32, f32)>]) -> Vec<T> {
+            if vs.is_empty() {
+                return vec![];
+            }
+            corners.iter().map(|corner| T::blend(vs, corner)).collect()
+        }
+        Triangle {
+            points: mix(&self.points, corners),
+            normal: mix(&self.normal, corners)
+                .into_iter()
+                .map(|n| n.normalize_or_zero())
+                .collect(),
+            uv: mix(&self.uv, corners),
+            tangent: mix(&self.tangent, corners)
+                .into_iter()
+                .map(|t| t.truncate().normalize_or_zero().extend(t.w))
+                .collect(),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(attribute, values)| (attribute.clone(), values_blend(values, corners)))
+                .collect(),
+            indices: None,
+        }
+    }
+
+    // 每个三角形按边分成 subdivisions 段, 共 subdivisions^2 个小三角形,
+    // 所有顶点属性按重心坐标插值
+    pub fn patch(&self, subdivisions: u32) -> Triangle {
+        if subdivisions <= 1 {
+            return self.clone();
+        }
+        let n = subdivisions;
+        let scale = 1.0 / n as f32;
+        let mut corners: Vec<Vec<(u32, f32)>> = Vec::new();
+        for tri in self.index_list().chunks_exact(3) {
+            // 网格点 (i, j) = a * (n - i - j) + b * i + c * j
+            let grid = |i: u32, j: u32| {
+                vec![
+                    (tri[0], (n - i - j) as f32 * scale),
+                    (tri[1], i as f32 * scale),
+                    (tri[2], j as f32 * scale),
+                ]
+            };
+            for i in 0..n {
+                for j in 0..n - i {
+                    corners.extend([grid(i, j), grid(i + 1, j), grid(i, j + 1)]);
+                    if i + j + 2 <= n {
+                        corners.extend([grid(i + 1, j), grid(i + 1, j + 1), grid(i, j + 1)]);
+                    }
+                }
+            }
+        }
+        self.blend(&corners).weld()
+    }
+
+    // Loop 细分: 每层把三角形分成 4 个并平滑位置, 其它属性线性插值.
+    // 位置相同的顶点视为同一个点, 所以 uv 接缝和硬边不会裂开
+    pub fn loop_subdivide(&self, levels: u32) -> Triangle {
+        let mut r = self.clone();
+        for _ in 0..levels {
+            r = r.loop_level();
+        }
+        if self.normal.is_empty() {
+            r
+        } else {
+            r.smooth_normals(std::f32::consts::PI)
+        }
+    }
+
+    fn loop_level(&self) -> Triangle {
+        let indices = self.index_list();
+        let position_key = |i: u32| {
+            self.points[i as usize]
+                .to_array()
+                .map(|v| (v + 0.0).to_bits())
+        };
+
+        // 按位置编号
+        let mut ids: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut id_points: Vec<Vec3> = Vec::new();
+        let vertex_id: Vec<usize> = (0..self.points.len() as u32)
+            .map(|i| {
+                *ids.entry(position_key(i)).or_insert_with(|| {
+                    id_points.push(self.points[i as usize]);
+                    id_points.len() - 1
+                })
+            })
+            .collect();
+
+        // 每条边对面的顶点, 只有一个时是边界
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for tri in indices.chunks_exact(3) {
+            let t = [tri[0], tri[1], tri[2]].map(|i| vertex_id[i as usize]);
+            for k in 0..3 {
+                let (a, b, c) = (t[k], t[(k + 1) % 3], t[(k + 2) % 3]);
+                edges.entry((a.min(b), a.max(b))).or_default().push(c);
+            }
+        }
+
+        // 原顶点的新位置
+        let mut neighbors: Vec<Vec<usize>> = vec![vec![]; id_points.len()];
+        let mut boundary: Vec<Vec<usize>> = vec![vec![]; id_points.len()];
+        for ((a, b), opposite) in edges.iter() {
+            neighbors[*a].push(*b);
+            neighbors[*b].push(*a);
+            if opposite.len() == 1 {
+                boundary[*a].push(*b);
+                boundary[*b].push(*a);
+            }
+        }
+        let smoothed: Vec<Vec3> = (0..id_points.len())
+            .map(|v| {
+                let p = id_points[v];
+                if boundary[v].len() == 2 {
+                    return p * 0.75
+                        + (id_points[boundary[v][0]] + id_points[boundary[v][1]]) * 0.125;
+                }
+                if !boundary[v].is_empty() || neighbors[v].is_empty() {
+                    return p;
+                }
+                let k = neighbors[v].len() as f32;
+                let beta = if neighbors[v].len() == 3 {
+                    3.0 / 16.0
+                } else {
+                    3.0 / (8.0 * k)
+                };
+                let sum: Vec3 = neighbors[v].iter().map(|n| id_points[*n]).sum();
+                p * (1.0 - k * beta) + sum * beta
+            })
+            .collect();
+
+        // 边中点的新位置
+        let edge_point = |a: usize, b: usize| -> Vec3 {
+            let opposite = &edges[&(a.min(b), a.max(b))];
+            if opposite.len() == 2 {
+                (id_points[a] + id_points[b]) * 0.375
+                    + (id_points[opposite[0]] + id_points[opposite[1]]) * 0.125
+            } else {
+                (id_points[a] + id_points[b]) * 0.5
+            }
+        };
+
+        let mut corners: Vec<Vec<(u32, f32)>> = Vec::new();
+        let mut positions: Vec<Vec3> = Vec::new();
+        for tri in indices.chunks_exact(3) {
+            let t = [tri[0], tri[1], tri[2]].map(|i| vertex_id[i as usize]);
+            let mid = |k: usize| {
+                let l = (k + 1) % 3;
+                (vec![(tri[k], 0.5), (tri[l], 0.5)], edge_point(t[k], t[l]))
+            };
+            let corner = |k: usize| (vec![(tri[k], 1.0)], smoothed[t[k]]);
+            let (m01, m12, m20) = (mid(0), mid(1), mid(2));
+            for vertex in [
+                corner(0),
+                m01.clone(),
+                m20.clone(),
+                m01.clone(),
+                corner(1),
+                m12.clone(),
+                m20.clone(),
+                m12.clone(),
+                corner(2),
+                m01,
+                m12,
+                m20,
+            ] {
+                corners.push(vertex.0);
+                positions.push(vertex.1);
+            }
+        }
+
+        let mut r = self.blend(&corners);
+        r.points = positions;
+        r.weld()
+    }
+
+    pub fn build(self) -> Mesh {
+        let indices: Vec<u32> = self.index_list();
+
+        let points_array: Vec<[f32; 3]> = self
+            .points
+            .into_iter()
+            .map(|v3| [v3.x, v3.y, v3.z])
+            .collect();
+        let normal_array: Vec<[f32; 3]> = self
+            .normal
+            .into_iter()
+            .map(|v3| [v3.x, v3.y, v3.z])
+            .collect();
+        let uv0_array: Vec<[f32; 2]> = self.uv.into_iter().map(|v3| [v3.x, v3.y]).collect();
+        let tangent_array: Vec<[f32; 4]> =
+            self.tangent.into_iter().map(|v4| v4.to_array()).collect();
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, points_array)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normal_array)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uv0_array)
+        .with_inserted_indices(Indices::U32(indices));
+        if !tangent_array.is_empty() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangent_array);
+        }
+        for (attribute, values) in self.attributes {
+            mesh.insert_attribute(attribute, values);
+        }
+        return mesh;
+    }
+
+    // 把 other 接在后面, other 的索引加上已有的顶点数
+    fn append(&mut self, other: Triangle) {
+        if self.indices.is_some() || other.indices.is_some() {
+            let offset = self.points.len() as u32;
+            let other_indices = other.index_list();
+            let indices = self.indices.get_or_insert_with(|| (0..offset).collect());
+            indices.extend(other_indices.into_iter().map(|i| i + offset));
+        }
+        // 只有一边有的属性, 另一边补默认值
+        let self_len = self.points.len();
+        let other_len = other.points.len();
+        for (attribute, values) in other.attributes.iter() {
+            if !self.attributes.iter().any(|(a, _)| a.id == attribute.id) {
+                let mut empty = values_pick(values, &[]);
+                values_resize(&mut empty, self_len);
+                self.attributes.push((attribute.clone(), empty));
+            }
+        }
+        for (attribute, values) in self.attributes.iter_mut() {
+            match other.attributes.iter().find(|(a, _)| a.id == attribute.id) {
+                Some((_, other_values)) => values_extend(values, other_values, other_len),
+                None => values_resize(values, self_len + other_len),
+            }
+        }
+
+        self.points.extend(other.points);
+        self.normal.extend(other.normal);
+        self.uv.extend(other.uv);
+        self.tangent.extend(other.tangent);
+    }
+
+    // 交换每个三角形的后两个顶点
+    fn flip_winding(&mut self) {
+        fn flip<T>(vs: &mut [T]) {
+            vs.chunks_exact_mut(3).for_each(|tri| tri.swap(1, 2));
+        }
+        match self.indices.as_mut() {
+            Some(indices) => flip(indices),
+            None => {
+                flip(&mut self.points);
+                flip(&mut self.normal);
+                flip(&mut self.uv);
+                flip(&mut self.tangent);
+                for (_, values) in self.attributes.iter_mut() {
+                    for tri in 0..values.len() / 3 {
+                        values_swap(values, tri * 3 + 1, tri * 3 + 2);
+                    }
+                }
+            }
+        }
+    }
+
+    // 位置用仿射变换, 法线用逆转置矩阵, 切线用线性部分; 镜像变换时翻转三角形绕序
+    fn transformed(&self, transform: &Transform) -> Triangle {
+        let affine = transform.compute_affine();
+        let linear = affine.matrix3;
+        let normal_matrix = linear.inverse().transpose();
+        let mirrored = linear.determinant() < 0.0;
+
+        let mut r = Triangle {
+            points: self
+                .points
+                .iter()
+                .map(|it| affine.transform_point3a((*it).into()).into())
+                .collect(),
+            normal: self
+                .normal
+                .iter()
+                .map(|it| Vec3::from(normal_matrix * Vec3A::from(*it)).normalize_or_zero())
+                .collect(),
+            uv: self.uv.clone(),
+            tangent: self
+                .tangent
+                .iter()
+                .map(|it| {
+                    let t = Vec3::from(linear * Vec3A::from(it.truncate())).normalize_or_zero();
+                    let w = if mirrored { -it.w } else { it.w };
+                    t.extend(w)
+                })
+                .collect(),
+            attributes: self.attributes.clone(),
+            indices: self.indices.clone(),
+        };
+        if mirrored {
+            r.flip_winding();
+        }
+        r
+    }
+}
+
+// 把很多网格合并成一个, 预先分配容量, 追加时不复制已有数据
+pub struct MeshBatcher {
+    batch: Triangle,
+}
+
+impl MeshBatcher {
+    pub fn new() -> MeshBatcher {
+        MeshBatcher::with_capacity(0, 0)
+    }
+
+    pub fn with_capacity(vertices: usize, indices: usize) -> MeshBatcher {
+        MeshBatcher {
+            batch: Triangle {
+                points: Vec::with_capacity(vertices),
+                normal: Vec::with_capacity(vertices),
+                uv: Vec::with_capacity(vertices),
+                tangent: vec![],
+                attributes: vec![],
+                indices: Some(Vec::with_capacity(indices)),
+            },
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.batch.points.len()
+    }
+
+    pub fn push_triangle(&mut self, triangle: &Triangle, transform: Transform) {
+        self.batch.append(triangle.transformed(&transform));
+    }
+
+    pub fn push_mesh(&mut self, mesh: &Mesh, transform: Transform) {
+        self.batch
+            .append(Triangle::from_mesh(mesh).transformed(&transform));
+    }
+
+    pub fn into_triangle(self) -> Triangle {
+        self.batch
+    }
+
+    pub fn build(self) -> Mesh {
+        self.batch.build()
+    }
+}
+
+impl Default for MeshBatcher {
+    fn default() -> Self {
+        MeshBatcher::new()
+    }
+}
+
+// 按权重混合顶点, 浮点数加权平均, 整数 (骨骼索引等) 取权重最大的
+trait Blend: Copy + Default {
+    fn blend(vs: &[Self], weights: &[(u32, f32)]) -> Self;
+}
+
+fn heaviest<T: Copy + Default>(vs: &[T], weights: &[(u32, f32)]) -> T {
+    weights
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .and_then(|(i, _)| vs.get(*i as usize).copied())
+        .unwrap_or_default()
+}
+
+macro_rules! blend_float {
+    ($($ty:ty),*) => {
+        $(impl Blend for $ty {
+            fn blend(vs: &[Self], weights: &[(u32, f32)]) -> Self {
+                weights
+                    .iter()
+                    .map(|(i, w)| vs.get(*i as usize).copied().unwrap_or_default() * *w)
+                    .sum()
+            }
+        })*
+    };
+}
+
+blend_float!(f32, Vec3, Vec4);
+
+impl<const N: usize> Blend for [f32; N]
+where
+    [f32; N]: Default,
+{
+    fn blend(vs: &[Self], weights: &[(u32, f32)]) -> Self {
+        let mut r = [0.0; N];
+        for (i, w) in weights {
+            if let Some(v) = vs.get(*i as usize) {
+                r.iter_mut().zip(v.iter()).for_each(|(r, v)| *r += v * w);
+            }
+        }
+        r
+    }
+}
+
+macro_rules! blend_heaviest {
+    ($($ty:ty),*) => {
+        $(impl Blend for $ty {
+            fn blend(vs: &[Self], weights: &[(u32, f32)]) -> Self {
+                heaviest(vs, weights)
+            }
+        })*
+    };
+}
+
+blend_heaviest!(
+    i32, u32, [i32; 2], [u32; 2], [i32; 3], [u32; 3], [i32; 4], [u32; 4], [i16; 2], [u16; 2],
+    [i16; 4], [u16; 4], [i8; 2], [u8; 2], [i8; 4], [u8; 4]
+);
+
+// 对 VertexAttributeValues 的每种格式生成同样的操作
+macro_rules! vertex_values_ops {
+    ($($variant:ident),*) => {
+        // 按索引取出顶点, 越界时取默认值
+        fn values_pick(values: &VertexAttributeValues, indices: &[u32]) -> VertexAttributeValues {
+            match values {
+                $(VertexAttributeValues::$variant(vs) => VertexAttributeValues::$variant(
+                    indices
+                        .iter()
+                        .map(|i| vs.get(*i as usize).copied().unwrap_or_default())
+                        .collect(),
+                ),)*
+            }
+        }
+
+        // 格式不同时按默认值补齐
+        fn values_extend(values: &mut VertexAttributeValues, other: &VertexAttributeValues, other_len: usize) {
+            match (values, other) {
+                $((VertexAttributeValues::$variant(a), VertexAttributeValues::$variant(b)) => {
+                    a.extend_from_slice(b)
+                })*
+                (values, _) => {
+                    let len = values.len() + other_len;
+                    values_resize(values, len)
+                }
+            }
+        }
+
+        fn values_blend(values: &VertexAttributeValues, corners: &[Vec<(u32, f32)>]) -> VertexAttributeValues {
+            match values {
+                $(VertexAttributeValues::$variant(vs) => VertexAttributeValues::$variant(
+                    corners.iter().map(|corner| Blend::blend(vs, corner)).collect(),
+                ),)*
+            }
+        }
+
+        fn values_resize(values: &mut VertexAttributeValues, len: usize) {
+            match values {
+                $(VertexAttributeValues::$variant(vs) => vs.resize(len, Default::default()),)*
+            }
+        }
+
+        fn values_swap(values: &mut VertexAttributeValues, i: usize, j: usize) {
+            match values {
+                $(VertexAttributeValues::$variant(vs) => vs.swap(i, j),)*
+            }
+        }
+    };
+}
+
+vertex_values_ops!(
+    Float32, Sint32, Uint32, Float32x2, Sint32x2, Uint32x2, Float32x3, Sint32x3, Uint32x3,
+    Float32x4, Sint32x4, Uint32x4, Sint16x2, Snorm16x2, Uint16x2, Unorm16x2, Sint16x4, Snorm16x4,
+    Uint16x4, Unorm16x4, Sint8x2, Snorm8x2, Uint8x2, Unorm8x2, Sint8x4, Snorm8x4, Uint8x4,
+    Unorm8x4
+);
+
+impl Add<Triangle> for Triangle {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        // rhs 在前
+        let mut r = rhs;
+        r.append(self);
+        r
+    }
+}
+
+impl Mul<Transform> for Triangle {
+    type Output = Triangle;
+    #[inline]
+    fn mul(self, trans: Transform) -> Triangle {
+        self.transformed(&trans)
+    }
+}
+
+impl Mul<Vec<Transform>> for Triangle {
+    type Output = Triangle;
+    #[inline]
+    fn mul(self, transforms: Vec<Transform>) -> Triangle {
+        let mut r = Triangle {
+            points: Vec::with_capacity(self.points.len() * transforms.len()),
+            normal: Vec::with_capacity(self.normal.len() * transforms.len()),
+            uv: Vec::with_capacity(self.uv.len() * transforms.len()),
+            tangent: Vec::with_capacity(self.tangent.len() * transforms.len()),
+            attributes: vec![],
+            indices: self.indices.as_ref().map(|_| Vec::new()),
+        };
+        for transform in transforms {
+            r.append(self.transformed(&transform));
+        }
+        r
+    }
+}
+
+impl Triangle {
+    pub fn pieces(num: u32) -> Triangle {
+        let mut points: Vec<Vec3> = Vec::new();
+        let t_points: Vec<Vec3> = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.01, 0.01, 0.0),
+            Vec3::new(0.0, 0.01, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.01, 0.0, 0.0),
+            Vec3::new(0.01, 0.01, 0.0),
+        ];
+        for i in 0..num {
+            let new_points: Vec<Vec3> = t_points
+                .iter()
+                .map(|vec3| vec3 + Vec3::new(0.0, 0.01 * i as f32, 0.0))
+                .collect();
+
+            points.extend(new_points);
+        }
+
+        let mut normal: Vec<Vec3> = Vec::new();
+        for i in 0..num {
+            normal.extend(vec![
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ]);
+        }
+
+        let mut uv: Vec<Vec3> = Vec::new();
+        let per_uv_length = 1.0 / num as f32;
+        for i in 0..num {
+            let feet: f32 = per_uv_length * i as f32;
+            let head: f32 = per_uv_length * (i+1) as f32;
+            uv.extend(vec![
+                Vec3::new(0.0, feet, 0.0),
+                Vec3::new(1.0, head, 0.0),
+                Vec3::new(0.0, head, 0.0),
+                Vec3::new(0.0, feet, 0.0),
+                Vec3::new(1.0, feet, 0.0),
+                Vec3::new(1.0, head, 0.0),
+            ]);
+        }
+
+        Triangle {
+            points: points,
+            normal: normal,
+            uv: uv,
+            tangent: vec![],
+            attributes: vec![],
+            indices: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from() {
+        Triangle::new(
+            vec![Vec3::ZERO, Vec3::ZERO, Vec3::ZERO],
+            vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_patch() {
+        let tri = Triangle::new(
+            vec![
+                Vec3::ZERO,
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -2.0),
+            ],
+            vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+        );
+        let patched = tri.patch(2);
+        let soup = patched.unindexed();
+        assert_eq!(soup.points.len(), 4 * 3);
+        // 6 个网格点
+        assert_eq!(patched.points.len(), 6);
+
+        // 面积不变, 绕序不变
+        let area = |t: &Triangle| -> Vec3 {
+            t.points
+                .chunks_exact(3)
+                .map(|p| (p[1] - p[0]).cross(p[2] - p[0]) * 0.5)
+                .sum()
+        };
+        assert!(area(&soup).distance(area(&tri)) < 1e-5);
+        for n in soup.face_normals() {
+            assert!(n.distance(Vec3::Y) < 1e-5);
+        }
+
+        // 属性按重心坐标插值: uv 和位置线性对应, 法线是单位向量
+        for ((p, uv), n) in soup
+            .points
+            .iter()
+            .zip(soup.uv.iter())
+            .zip(soup.normal.iter())
+        {
+            assert!(uv.distance(Vec3::new(p.x / 2.0, -p.z / 2.0, 0.0)) < 1e-5);
+            assert!((n.length() - 1.0).abs() < 1e-5);
+        }
+        let mid = patched
+            .points
+            .iter()
+            .position(|p| p.distance(Vec3::new(1.0, 0.0, -1.0)) < 1e-5)
+            .unwrap();
+        assert!(patched.uv[mid].distance(Vec3::new(0.5, 0.5, 0.0)) < 1e-5);
+        assert!(patched.normal[mid].distance(Vec3::new(1.0, 1.0, 0.0).normalize()) < 1e-5);
+    }
+
+    #[test]
+    fn test_loop_subdivide() {
+        let cube = Triangle::from_mesh(&Cuboid::new(2.0, 2.0, 2.0).mesh().build());
+        let smooth = cube.loop_subdivide(2);
+        assert_eq!(smooth.index_list().len(), 36 * 16);
+
+        // 闭合网格向内收缩, 角被磨圆
+        for p in smooth.points.iter() {
+            assert!(p.abs().max_element() <= 1.0 + 1e-5);
+        }
+        let corner = smooth
+            .points
+            .iter()
+            .map(|p| p.length())
+            .fold(0f32, f32::max);
+        assert!(corner < 3f32.sqrt() * 0.9);
+
+        // 平面只细分不变形
+        let plane = Triangle::from_mesh(&Plane3d::default().mesh().size(2.0, 2.0).build());
+        for p in plane.loop_subdivide(1).points.iter() {
+            assert!(p.y.abs() < 1e-5);
+            assert!(p.x.abs() <= 1.0 + 1e-5 && p.z.abs() <= 1.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_weld() {
+        let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        assert_eq!(cube.points.len(), 24);
+        assert_eq!(cube.index_list().len(), 36);
+
+        let soup = cube.unindexed();
+        assert_eq!(soup.points.len(), 36);
+        assert!(soup.indices.is_none());
+
+        let welded = soup.weld();
+        assert_eq!(welded.points.len(), 24);
+        assert_eq!(welded.unindexed().points, soup.points);
+
+        // 两个副本的索引不会重叠
+        let two = cube * vec![Transform::IDENTITY, Transform::from_xyz(2.0, 0.0, 0.0)];
+        assert_eq!(two.points.len(), 48);
+        assert_eq!(two.index_list().iter().max(), Some(&47));
+    }
+
+    // 随机的旋转, 非均匀缩放(含镜像)和平移
+    fn random_transform(rng: &mut impl rand::Rng) -> Transform {
+        let mut scale = Vec3::new(
+            rng.gen_range(0.2..3.0),
+            rng.gen_range(0.2..3.0),
+            rng.gen_range(0.2..3.0),
+        );
+        if rng.gen_bool(0.5) {
+            scale.x = -scale.x;
+        }
+        Transform::from_xyz(
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+        )
+        .with_rotation(Quat::from_euler(
+            EulerRot::XYZ,
+            rng.gen_range(-3.0..3.0),
+            rng.gen_range(-3.0..3.0),
+            rng.gen_range(-3.0..3.0),
+        ))
+        .with_scale(scale)
+    }
+
+    fn face_normal(points: &[Vec3]) -> Vec3 {
+        (points[1] - points[0])
+            .cross(points[2] - points[0])
+            .normalize()
+    }
+
+    #[test]
+    fn test_transform_normal() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(32);
+        for _ in 0..200 {
+            let points: Vec<Vec3> = (0..3)
+                .map(|_| {
+                    Vec3::new(
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                    )
+                })
+                .collect();
+            if (points[1] - points[0])
+                .cross(points[2] - points[0])
+                .length()
+                < 0.05
+            {
+                continue;
+            }
+            let normal = face_normal(&points);
+            let tangent = (points[1] - points[0]).normalize().extend(1.0);
+            let mut tri = Triangle::new(points, vec![normal; 3], vec![Vec3::ZERO; 3]);
+            tri.tangent = vec![tangent; 3];
+
+            let transform = random_transform(&mut rng);
+            let mirrored = transform.scale.x * transform.scale.y * transform.scale.z < 0.0;
+            let r = tri.clone() * transform;
+
+            // 法线和变换后的面法线一致, 说明法线正确且绕序没有反
+            let expected = face_normal(&r.points);
+            for n in r.normal.iter() {
+                assert!((n.length() - 1.0).abs() < 1e-3);
+                assert!(n.dot(expected) > 0.999, "{n} {expected}");
+            }
+            // 切线仍在面内, 镜像时副切线方向取反
+            for t in r.tangent.iter() {
+                assert!(t.truncate().dot(expected).abs() < 1e-3);
+                assert_eq!(t.w, if mirrored { -1.0 } else { 1.0 });
+            }
+
+            // 带索引和不带索引的结果相同
+            let indexed = Triangle::new_indexed(
+                tri.points.clone(),
+                tri.normal.clone(),
+                tri.uv.clone(),
+                vec![0, 1, 2],
+            );
+            let r_indexed = (indexed * vec![transform]).unindexed();
+            for (a, b) in r_indexed.points.iter().zip(r.points.iter()) {
+                assert!(a.distance(*b) < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut mesh = Cuboid::new(1.0, 1.0, 1.0).mesh().build();
+        let vertex_count = mesh.count_vertices();
+        let colors: Vec<[f32; 4]> = (0..vertex_count)
+            .map(|i| [i as f32, 0.0, 0.0, 1.0])
+            .collect();
+        let joints: Vec<[u16; 4]> = (0..vertex_count).map(|i| [i as u16, 1, 2, 3]).collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors.clone());
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_JOINT_INDEX,
+            VertexAttributeValues::Uint16x4(joints),
+        );
+
+        let cube = Triangle::from_mesh(&mesh);
+        assert_eq!(cube.attributes.len(), 2);
+
+        // 两个副本的属性都在, 颜色按顶点对应
+        let mirrored = Transform::from_scale(Vec3::new(-1.0, 1.0, 1.0));
+        let built = (cube.clone() * vec![Transform::IDENTITY, mirrored]).build();
+        assert_eq!(built.count_vertices(), vertex_count * 2);
+        let Some(VertexAttributeValues::Float32x4(built_colors)) =
+            built.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("color lost");
+        };
+        assert_eq!(built_colors[..vertex_count], colors[..]);
+        assert!(matches!(
+            built.attribute(Mesh::ATTRIBUTE_JOINT_INDEX),
+            Some(VertexAttributeValues::Uint16x4(_))
+        ));
+
+        // 没有颜色的一边补默认值
+        let plain = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        let merged = plain + cube.unindexed();
+        let merged_len = merged.points.len();
+        for (_, values) in merged.attributes.iter() {
+            assert_eq!(values.len(), merged_len);
+        }
+
+        // 颜色不同的顶点不合并
+        assert_eq!(cube.unindexed().weld().points.len(), vertex_count);
+    }
+
+    #[test]
+    fn test_normals() {
+        let cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+
+        // 立方体的面夹角 90 度, 小于折角时保持硬边
+        let hard = cube.smooth_normals(30f32.to_radians()).unindexed();
+        for (n, expected) in hard.normal.iter().zip(cube.flat_normals().normal.iter()) {
+            assert!(n.distance(*expected) < 1e-5);
+        }
+        // 全部平滑时角上的法线指向对角线
+        let smooth = cube.smooth_normals(std::f32::consts::PI);
+        for (p, n) in smooth.points.iter().zip(smooth.normal.iter()) {
+            assert!(n.distance(p.normalize()) < 1e-5);
+        }
+
+        // 倾斜的平面
+        let slope = Triangle::new(
+            vec![
+                Vec3::ZERO,
+                Vec3::new(0.0, 1.0, 1.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+            vec![],
+            vec![],
+        );
+        let expected = Vec3::new(0.0, 1.0, -1.0).normalize();
+        for n in slope.flat_normals().normal.iter() {
+            assert!(n.distance(expected) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_generate_tangents() {
+        let mut cube = Triangle::from_mesh(&Cuboid::new(1.0, 1.0, 1.0).mesh().build());
+        assert!(cube.generate_tangents().is_ok());
+        assert_eq!(cube.tangent.len(), cube.points.len());
+
+        // 没有 uv 时返回错误
+        let mut no_uv = cube.flat_normals();
+        no_uv.uv.clear();
+        no_uv.tangent.clear();
+        assert!(no_uv.generate_tangents().is_err());
+        assert!(no_uv.tangent.is_empty());
+    }
+}
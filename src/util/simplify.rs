@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bevy::math::DVec3;
+use bevy::render::mesh::Mesh;
+
+use super::Triangle;
+
+// 二次误差矩阵 (对称 4x4, 存上三角)
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // 平面 n·p + d = 0, 按面积加权
+    fn plane(n: DVec3, d: f64, weight: f64) -> Quadric {
+        let (a, b, c) = (n.x, n.y, n.z);
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|v| v * weight),
+        )
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut r = self.0;
+        r.iter_mut().zip(other.0.iter()).for_each(|(a, b)| *a += b);
+        Quadric(r)
+    }
+
+    // 点到所有平面的距离平方和
+    fn error(&self, p: DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+// 候选的折叠: 把位置 from 合并到位置 to
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // 代价小的先出堆
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl Triangle {
+    // 二次误差度量的半边折叠简化, 直到三角形数不超过 target_triangles,
+    // 或者下一次折叠的误差 (距离平方和) 超过 max_error.
+    // uv / 法线接缝上的顶点和边界顶点不会移动, 所以接缝和区块边缘保持不变;
+    // 焊接后平直着色的共面三角形内部没有接缝, 仍然可以折叠.
+    // 折叠时保留下来的顶点沿用自己原来的法线
+    pub fn simplify(&self, target_triangles: usize, max_error: f32) -> Triangle {
+        let welded = self.weld();
+        let mut faces: Vec<[u32; 3]> = welded
+            .index_list()
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let mut alive = vec![true; faces.len()];
+        let mut face_count = faces.len();
+        if face_count <= target_triangles {
+            return welded;
+        }
+
+        // 按位置编号, 同一位置的多个顶点是接缝
+        let mut ids: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut positions: Vec<DVec3> = Vec::new();
+        let mut variants: Vec<Vec<u32>> = Vec::new();
+        let vertex_id: Vec<usize> = welded
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let id = *ids
+                    .entry(p.to_array().map(|v| (v + 0.0).to_bits()))
+                    .or_insert_with(|| {
+                        positions.push(p.as_dvec3());
+                        variants.push(vec![]);
+                        positions.len() - 1
+                    });
+                variants[id].push(i as u32);
+                id
+            })
+            .collect();
+        let face_ids = |face: &[u32; 3]| face.map(|i| vertex_id[i as usize]);
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut vertex_faces: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+        let mut edge_faces: HashMap<(usize, usize), u32> = HashMap::new();
+        for (f, face) in faces.iter().enumerate() {
+            let t = face_ids(face);
+            let [p0, p1, p2] = t.map(|id| positions[id]);
+            let cross = (p1 - p0).cross(p2 - p0);
+            let area = cross.length() * 0.5;
+            if let Some(n) = cross.try_normalize() {
+                let q = Quadric::plane(n, -n.dot(p0), area);
+                t.iter()
+                    .for_each(|id| quadrics[*id] = quadrics[*id].add(&q));
+            }
+            for k in 0..3 {
+                vertex_faces[t[k]].push(f);
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *edge_faces.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+
+        // 接缝和边界的位置锁定
+        let mut locked: Vec<bool> = variants.iter().map(|v| v.len() > 1).collect();
+        for ((a, b), count) in edge_faces.iter() {
+            if *count != 2 {
+                locked[*a] = true;
+                locked[*b] = true;
+            }
+        }
+
+        let mut versions = vec![0u32; positions.len()];
+        let mut heap: BinaryHeap<Collapse> = BinaryHeap::new();
+        let push_edges = |heap: &mut BinaryHeap<Collapse>,
+                          id: usize,
+                          faces: &Vec<[u32; 3]>,
+                          alive: &Vec<bool>,
+                          vertex_faces: &Vec<Vec<usize>>,
+                          quadrics: &Vec<Quadric>,
+                          versions: &Vec<u32>| {
+            let mut neighbors: HashSet<usize> = HashSet::new();
+            for f in vertex_faces[id].iter().filter(|f| alive[**f]) {
+                neighbors.extend(face_ids(&faces[*f]));
+            }
+            neighbors.remove(&id);
+            for other in neighbors {
+                for (from, to) in [(id, other), (other, id)] {
+                    if locked[from] {
+                        continue;
+                    }
+                    heap.push(Collapse {
+                        cost: quadrics[from].add(&quadrics[to]).error(positions[to]),
+                        from,
+                        to,
+                        versions: (versions[from], versions[to]),
+                    });
+                }
+            }
+        };
+        for id in 0..positions.len() {
+            push_edges(
+                &mut heap,
+                id,
+                &faces,
+                &alive,
+                &vertex_faces,
+                &quadrics,
+                &versions,
+            );
+        }
+
+        while face_count > target_triangles {
+            let Some(collapse) = heap.pop() else {
+                break;
+            };
+            let (from, to) = (collapse.from, collapse.to);
+            if collapse.versions != (versions[from], versions[to]) {
+                continue;
+            }
+            if collapse.cost > max_error as f64 {
+                break;
+            }
+
+            // from 不在接缝上, 只有一个顶点; to 取共享三角形里用到的那个顶点
+            let from_vertex = variants[from][0];
+            let around: Vec<usize> = vertex_faces[from]
+                .iter()
+                .copied()
+                .filter(|f| alive[*f])
+                .collect();
+            let shared: Vec<usize> = around
+                .iter()
+                .copied()
+                .filter(|f| face_ids(&faces[*f]).contains(&to))
+                .collect();
+            let to_vertices: HashSet<u32> = shared
+                .iter()
+                .flat_map(|f| faces[*f])
+                .filter(|i| vertex_id[*i as usize] == to)
+                .collect();
+            if shared.is_empty() || to_vertices.len() != 1 {
+                continue;
+            }
+            let to_vertex = *to_vertices.iter().next().unwrap();
+
+            // 两端共同的邻居只能是共享三角形的对角, 否则会产生非流形
+            let neighbors = |id: usize| -> HashSet<usize> {
+                vertex_faces[id]
+                    .iter()
+                    .filter(|f| alive[**f])
+                    .flat_map(|f| face_ids(&faces[*f]))
+                    .filter(|n| *n != id)
+                    .collect()
+            };
+            let common = neighbors(from).intersection(&neighbors(to)).count();
+            if common != shared.len() {
+                continue;
+            }
+
+            // 移动后三角形不能翻转或退化
+            let flips = around.iter().filter(|f| !shared.contains(f)).any(|f| {
+                let t = face_ids(&faces[*f]);
+                let before = t.map(|id| positions[id]);
+                let after = t.map(|id| {
+                    if id == from {
+                        positions[to]
+                    } else {
+                        positions[id]
+                    }
+                });
+                let n0 = (before[1] - before[0]).cross(before[2] - before[0]);
+                let n1 = (after[1] - after[0]).cross(after[2] - after[0]);
+                n1.length_squared() <= f64::EPSILON * n0.length_squared() || n0.dot(n1) <= 0.0
+            });
+            if flips {
+                continue;
+            }
+
+            for f in around {
+                if shared.contains(&f) {
+                    alive[f] = false;
+                    face_count -= 1;
+                } else {
+                    faces[f].iter_mut().for_each(|i| {
+                        if *i == from_vertex {
+                            *i = to_vertex;
+                        }
+                    });
+                    vertex_faces[to].push(f);
+                }
+            }
+            quadrics[to] = quadrics[to].add(&quadrics[from]);
+            versions[from] += 1;
+            versions[to] += 1;
+            push_edges(
+                &mut heap,
+                to,
+                &faces,
+                &alive,
+                &vertex_faces,
+                &quadrics,
+                &versions,
+            );
+        }
+
+        // 去掉没用到的顶点, 顶点数据原样保留
+        let indices: Vec<u32> = faces
+            .iter()
+            .zip(alive.iter())
+            .filter(|(_, alive)| **alive)
+            .flat_map(|(face, _)| *face)
+            .collect();
+        let mut simple = welded;
+        simple.indices = Some(indices);
+        simple.weld()
+    }
+}
+
+pub fn simplify_mesh(mesh: &Mesh, target_triangles: usize, max_error: f32) -> Mesh {
+    Triangle::from_mesh(mesh)
+        .simplify(target_triangles, max_error)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::*;
+
+    #[test]
+    fn test_simplify_plane() {
+        let plane = Triangle::from_mesh(
+            &Plane3d::default()
+                .mesh()
+                .size(2.0, 2.0)
+                .subdivisions(9)
+                .build(),
+        );
+        assert_eq!(plane.index_list().len() / 3, 200);
+
+        let simple = plane.simplify(0, 1e-6);
+        let triangles = simple.index_list().len() / 3;
+        assert!(triangles < 100, "{triangles}");
+
+        // 平面和边界不变, 法线朝上
+        let area: f32 = simple
+            .unindexed()
+            .points
+            .chunks_exact(3)
+            .map(|p| (p[1] - p[0]).cross(p[2] - p[0]).y * 0.5)
+            .sum();
+        assert!((area - 4.0).abs() < 1e-4, "{area}");
+        for p in simple.points.iter() {
+            assert!(p.y.abs() < 1e-6);
+        }
+        for n in simple.face_normals() {
+            assert!(n.dot(Vec3::Y) > 0.999);
+        }
+    }
+
+    #[test]
+    fn test_simplify_sphere() {
+        let sphere = Triangle::from_mesh(&Sphere::new(1.0).mesh().ico(3).unwrap());
+        let before = sphere.index_list().len() / 3;
+        let simple = sphere.simplify(before / 2, f32::MAX);
+        let after = simple.index_list().len() / 3;
+        assert!(after < before * 3 / 4, "{before} -> {after}");
+        // 顶点仍在原来的位置上
+        for p in simple.points.iter() {
+            assert!((p.length() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    // 没有顶面的盒子, 每个面 n*n 格, 平直法线, 每个面单独的 uv
+    fn open_box(n: usize) -> Triangle {
+        let faces = [
+            (Vec3::X, Vec3::Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Y, Vec3::Z),
+            (Vec3::Z, Vec3::Y, Vec3::X),
+            (Vec3::NEG_Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Y, Vec3::Z, Vec3::X),
+        ];
+        let (mut points, mut normal, mut uv) = (vec![], vec![], vec![]);
+        for (n_dir, u_dir, v_dir) in faces {
+            // u × v 和法线同向
+            let (u_dir, v_dir) = if u_dir.cross(v_dir).dot(n_dir) > 0.0 {
+                (u_dir, v_dir)
+            } else {
+                (v_dir, u_dir)
+            };
+            let corner = |i: usize, j: usize| {
+                let (a, b) = (i as f32 / n as f32, j as f32 / n as f32);
+                (
+                    n_dir * 0.5 + u_dir * (a - 0.5) + v_dir * (b - 0.5),
+                    Vec3::new(a, b, 0.0),
+                )
+            };
+            for i in 0..n {
+                for j in 0..n {
+                    for (di, dj) in [(0, 0), (1, 0), (1, 1), (0, 0), (1, 1), (0, 1)] {
+                        let (p, t) = corner(i + di, j + dj);
+                        points.push(p);
+                        normal.push(n_dir);
+                        uv.push(t);
+                    }
+                }
+            }
+        }
+        Triangle::new(points, normal, uv)
+    }
+
+    #[test]
+    fn test_simplify_flat_shaded() {
+        let open = open_box(4);
+        let before = open.points.len() / 3;
+        let simple = open.simplify(0, 1e-6);
+        let after = simple.index_list().len() / 3;
+        assert!(after < before / 2, "{before} -> {after}");
+
+        // uv 接缝 (盒子的棱) 和边界 (开口) 上的顶点都还在
+        let key = |p: &Vec3| p.to_array().map(|v| (v + 0.0).to_bits());
+        let kept: HashSet<[u32; 3]> = simple.points.iter().map(key).collect();
+        for p in open.points.iter() {
+            let on_edge = p.to_array().iter().filter(|v| v.abs() == 0.5).count() >= 2;
+            let on_rim = p.y == 0.5;
+            if on_edge || on_rim {
+                assert!(kept.contains(&key(p)), "{p}");
+            }
+        }
+        // 硬边仍然是硬边
+        for (n, face) in simple.normal.iter().zip(simple.points.iter()) {
+            assert!(n.abs().max_element() > 0.999, "{face} {n}");
+        }
+    }
+
+    #[test]
+    fn test_simplify_keeps_authored_normals() {
+        let key = |p: &Vec3| p.to_array().map(|v| (v + 0.0).to_bits());
+        let pairs = |mesh: &Triangle| -> HashSet<([u32; 3], [u32; 3])> {
+            mesh.points
+                .iter()
+                .zip(mesh.normal.iter())
+                .map(|(p, n)| (key(p), key(n)))
+                .collect()
+        };
+
+        // 平面上手工调过的弯曲法线, 折叠后留下的顶点法线不变
+        let mut plane = Triangle::from_mesh(
+            &Plane3d::default()
+                .mesh()
+                .size(2.0, 2.0)
+                .subdivisions(9)
+                .build(),
+        );
+        plane.normal = plane
+            .points
+            .iter()
+            .map(|p| Vec3::new(p.x, 2.0, p.z).normalize())
+            .collect();
+        let simple = plane.simplify(0, 1e-6);
+        assert!(simple.index_list().len() / 3 < 100);
+        assert!(pairs(&simple).is_subset(&pairs(&plane)));
+
+        // 只有法线不同的硬边也是接缝, 法线不会重新计算
+        let facets = Triangle::from_mesh(&Sphere::new(1.0).mesh().ico(2).unwrap()).flat_normals();
+        let simple = facets.simplify(0, f32::MAX);
+        assert!(pairs(&simple).is_subset(&pairs(&facets)));
+    }
+}
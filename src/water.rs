@@ -0,0 +1,376 @@
+use bevy::pbr::{DistanceFog, FogFalloff, NotShadowCaster};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::{Action, ActionState};
+use crate::locomotion::Flying;
+use crate::player::Player;
+use crate::region::StreamingAnchor;
+
+// 海平面以下都是水
+#[derive(Resource, Debug)]
+pub struct Water {
+    pub level: f32,
+    pub color: Color,
+    // 水下雾的浓度
+    pub fog_density: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Water {
+            // 出生点附近大约四分之一的地面在水下
+            level: -6.0,
+            color: Color::srgb(0.1, 0.3, 0.5),
+            fog_density: 0.08,
+        }
+    }
+}
+
+impl Water {
+    // 碰撞体 y 方向 [center - half, center + half] 在水下的比例
+    pub fn submerged_ratio(&self, center: f32, half_height: f32) -> f32 {
+        if half_height <= 0.0 {
+            return if center < self.level { 1.0 } else { 0.0 };
+        }
+        ((self.level - (center - half_height)) / (2.0 * half_height)).clamp(0.0, 1.0)
+    }
+}
+
+// 水面
+#[derive(Component)]
+pub struct WaterSurface;
+
+// 浸在水里的程度, 由 update_submersion 更新
+#[derive(Component, Default, Debug)]
+pub struct Submersion {
+    // 0 没入水, 1 完全在水下
+    pub ratio: f32,
+    // 碰撞体顶部也在水下
+    pub head_under: bool,
+}
+
+// 角色和 NPC 的游泳参数
+#[derive(Component, Clone, Debug)]
+pub struct SwimConfig {
+    // 浸没比例超过它开始游泳, 低于一半时回到地面移动
+    pub threshold: f32,
+    // 游泳时的重力倍数
+    pub gravity_scale: f32,
+    // 完全浸没时浮力是重力的几倍, 浮在 1 / buoyancy 的深度
+    pub buoyancy: f32,
+    // 每秒速度衰减的比例
+    pub drag: f32,
+    // 跳跃键上浮, 蹲下键下潜的速度
+    pub vertical_speed: f32,
+}
+
+impl Default for SwimConfig {
+    fn default() -> Self {
+        SwimConfig {
+            threshold: 0.5,
+            gravity_scale: 1.0,
+            buoyancy: 1.4,
+            drag: 3.0,
+            vertical_speed: 6.0,
+        }
+    }
+}
+
+// 正在游泳, 记下下水前的重力倍数, 上岸时恢复
+#[derive(Component, Debug)]
+pub struct Swimming {
+    dry_gravity_scale: f32,
+}
+
+// 刚体 (比如投出去的精灵球) 在水里受到的浮力
+#[derive(Component, Clone, Debug)]
+pub struct Buoyancy {
+    // 完全浸没时浮力是重力的几倍, 大于 1 会浮起来
+    pub buoyancy: f32,
+    pub gravity_scale: f32,
+    pub linear_drag: f32,
+    pub angular_drag: f32,
+}
+
+impl Default for Buoyancy {
+    fn default() -> Self {
+        Buoyancy {
+            buoyancy: 2.0,
+            gravity_scale: 1.0,
+            linear_drag: 2.0,
+            angular_drag: 1.0,
+        }
+    }
+}
+
+// 水下憋气
+#[derive(Component, Debug)]
+pub struct Breath {
+    // 剩余秒数
+    pub current: f32,
+    pub max: f32,
+    // 出水后每秒恢复
+    pub recovery: f32,
+}
+
+impl Default for Breath {
+    fn default() -> Self {
+        Breath {
+            current: 10.0,
+            max: 10.0,
+            recovery: 4.0,
+        }
+    }
+}
+
+impl Breath {
+    fn update(&mut self, head_under: bool, dt: f32) {
+        self.current = if head_under {
+            (self.current - dt).max(0.0)
+        } else {
+            (self.current + self.recovery * dt).min(self.max)
+        };
+    }
+
+    pub fn out_of_breath(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+// 憋气条
+#[derive(Component)]
+pub struct BreathMeter;
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let water = Water::default();
+
+    // 半透明水面, 跟着区块加载中心移动
+    commands.spawn((
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(400.0, 400.0))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: water.color.with_alpha(0.6),
+            alpha_mode: AlphaMode::Blend,
+            perceptual_roughness: 0.1,
+            // 从水下往上看也能看到水面
+            cull_mode: None,
+            ..default()
+        })),
+        Transform::from_xyz(0.0, water.level, 0.0),
+        NotShadowCaster,
+        WaterSurface,
+    ));
+
+    // 屏幕底部的憋气条, 在水下时显示
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(40.0),
+                left: Val::Percent(40.0),
+                width: Val::Percent(20.0),
+                height: Val::Px(8.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            Visibility::Hidden,
+            BreathMeter,
+        ))
+        .with_child((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.6, 0.85, 1.0)),
+        ));
+
+    commands.insert_resource(water);
+}
+
+pub fn follow_anchor(
+    water: Res<Water>,
+    anchor_query: Query<&Transform, (With<StreamingAnchor>, Without<WaterSurface>)>,
+    mut surface_query: Query<&mut Transform, With<WaterSurface>>,
+) {
+    let Ok(anchor) = anchor_query.single() else {
+        return;
+    };
+    for mut transform in surface_query.iter_mut() {
+        transform.translation = Vec3::new(anchor.translation.x, water.level, anchor.translation.z);
+    }
+}
+
+// 按碰撞体包围盒计算浸没比例, 有 SwimConfig 的实体切换游泳状态
+pub fn update_submersion(
+    mut commands: Commands,
+    water: Res<Water>,
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &mut Submersion,
+        Option<&SwimConfig>,
+        Option<&Swimming>,
+        Option<&mut GravityScale>,
+        Has<Flying>,
+    )>,
+) {
+    for (entity, transform, collider, mut submersion, swim, swimming, mut gravity_scale, flying) in
+        query.iter_mut()
+    {
+        // rapier 已经把 Transform 的缩放同步到碰撞体上
+        let half_height = collider.raw.compute_local_aabb().half_extents().y;
+        submersion.ratio = water.submerged_ratio(transform.translation.y, half_height);
+        submersion.head_under = transform.translation.y + half_height < water.level;
+
+        let Some(swim) = swim else {
+            continue;
+        };
+        match swimming {
+            // 飞行时不游泳, 保持没有重力
+            None if submersion.ratio >= swim.threshold && !flying => {
+                let dry_gravity_scale = gravity_scale.as_ref().map_or(1.0, |g| g.0);
+                commands
+                    .entity(entity)
+                    .insert(Swimming { dry_gravity_scale });
+            }
+            // 游泳中切换到飞行, 重力由 toggle_fly 设置, 不要恢复
+            Some(_) if flying => {
+                commands.entity(entity).remove::<Swimming>();
+            }
+            Some(swimming) if submersion.ratio < swim.threshold * 0.5 => {
+                if let Some(gravity_scale) = gravity_scale.as_mut() {
+                    gravity_scale.0 = swimming.dry_gravity_scale;
+                }
+                commands.entity(entity).remove::<Swimming>();
+            }
+            _ => {}
+        }
+    }
+}
+
+// 游泳: 重力变小, 浮力, 阻力; 角色用跳跃和蹲下键上下, NPC 在水下时自己往上游
+pub fn handle_swimming(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    mut query: Query<
+        (
+            &Submersion,
+            &SwimConfig,
+            &mut Velocity,
+            &mut GravityScale,
+            Has<Player>,
+        ),
+        (With<Swimming>, Without<Flying>),
+    >,
+) {
+    let dt = time.delta_secs();
+    for (submersion, swim, mut velocity, mut gravity_scale, is_player) in query.iter_mut() {
+        gravity_scale.0 = swim.gravity_scale * (1.0 - swim.buoyancy * submersion.ratio);
+
+        let vertical = if is_player {
+            actions.pressed(Action::Jump) as i32 - actions.pressed(Action::Crouch) as i32
+        } else {
+            submersion.head_under as i32
+        };
+        let drag = 1.0 - (-swim.drag * dt).exp();
+        let target = vertical as f32 * swim.vertical_speed;
+        if vertical != 0 {
+            velocity.linvel.y += (target - velocity.linvel.y) * drag;
+        } else {
+            velocity.linvel.y -= velocity.linvel.y * drag;
+        }
+        velocity.linvel.x -= velocity.linvel.x * drag;
+        velocity.linvel.z -= velocity.linvel.z * drag;
+    }
+}
+
+// 刚体的浮力用负的重力倍数实现, 水里加阻尼
+pub fn apply_buoyancy(mut query: Query<(&Submersion, &Buoyancy, &mut GravityScale, &mut Damping)>) {
+    for (submersion, buoyancy, mut gravity_scale, mut damping) in query.iter_mut() {
+        gravity_scale.0 = buoyancy.gravity_scale * (1.0 - buoyancy.buoyancy * submersion.ratio);
+        damping.linear_damping = buoyancy.linear_drag * submersion.ratio;
+        damping.angular_damping = buoyancy.angular_drag * submersion.ratio;
+    }
+}
+
+pub fn update_breath(
+    time: Res<Time>,
+    mut breath_query: Query<(&Submersion, &mut Breath), With<Player>>,
+    mut meter_query: Query<(&mut Visibility, &Children), With<BreathMeter>>,
+    mut fill_query: Query<&mut Node>,
+) {
+    let Ok((submersion, mut breath)) = breath_query.single_mut() else {
+        return;
+    };
+    breath.update(submersion.head_under, time.delta_secs());
+
+    let Ok((mut visibility, children)) = meter_query.single_mut() else {
+        return;
+    };
+    visibility.set_if_neq(if breath.current < breath.max {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if let Some(mut node) = children.first().and_then(|e| fill_query.get_mut(*e).ok()) {
+        node.width = Val::Percent(breath.current / breath.max * 100.0);
+    }
+}
+
+// 摄像机在水下时加一层蓝色的雾
+pub fn underwater_camera(
+    mut commands: Commands,
+    water: Res<Water>,
+    camera_query: Query<(Entity, &Transform, Has<DistanceFog>), With<Camera3d>>,
+) {
+    for (entity, transform, has_fog) in camera_query.iter() {
+        let under = transform.translation.y < water.level;
+        if under && !has_fog {
+            commands.entity(entity).insert(DistanceFog {
+                color: water.color,
+                falloff: FogFalloff::Exponential {
+                    density: water.fog_density,
+                },
+                ..default()
+            });
+        } else if !under && has_fog {
+            commands.entity(entity).remove::<DistanceFog>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submerged_ratio() {
+        let water = Water {
+            level: 0.0,
+            ..default()
+        };
+        assert_eq!(water.submerged_ratio(2.0, 1.0), 0.0);
+        assert_eq!(water.submerged_ratio(0.5, 1.0), 0.25);
+        assert_eq!(water.submerged_ratio(-3.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_breath() {
+        let mut breath = Breath::default();
+        for _ in 0..101 {
+            breath.update(true, 0.1);
+        }
+        assert!(breath.out_of_breath());
+        // 出水 2.5 秒恢复满
+        for _ in 0..26 {
+            breath.update(false, 0.1);
+        }
+        assert_eq!(breath.current, breath.max);
+    }
+}